@@ -114,12 +114,14 @@ fn build_key_holder_struct(item: &syn::ItemStruct) -> TokenStream {
     }
 
     let item_ident = &item.ident;
+    // The key holder is the key type of the topic, so it is as visible as the topic
+    let vis = &item.vis;
     //println!("Filtered fields:{:?}", &filtered_fields);
 
     let ts = quote! {
         #[derive(Default, Deserialize, Serialize, PartialEq, Clone)]
-        struct #holder_name {
-            #(#field_idents:#field_types,)*
+        #vis struct #holder_name {
+            #(#vis #field_idents:#field_types,)*
         }
 
         impl From<& #item_ident> for #holder_name {
@@ -152,13 +154,13 @@ fn create_keyhash_functions(item: &syn::ItemStruct, is_fixed_size: bool) -> Toke
 
     let ts = quote! {
         impl TopicType for #topic_key_ident {
+            type Key = #topic_key_holder_ident;
+
             /// return the cdr encoding for the key. The encoded string includes the four byte
             /// encapsulation string.
             fn key_cdr(&self) -> Vec<u8> {
                 let holder_struct : #topic_key_holder_ident = self.into();
-
-                let encoded = cdr::serialize::<_, _, cdr::CdrBe>(&holder_struct, cdr::Infinite).expect("Unable to serialize key");
-               encoded
+                Self::key_to_cdr(&holder_struct)
             }
 
             fn is_fixed_size() -> bool {
@@ -179,6 +181,15 @@ fn create_keyhash_functions(item: &syn::ItemStruct, is_fixed_size: bool) -> Toke
         }
     };
 
+    let ts = if is_fixed_size {
+        quote! {
+            #ts
+            impl TopicFixedSize for #topic_key_ident {}
+        }
+    } else {
+        ts
+    };
+
     ts.into()
 }
 
@@ -18,14 +18,16 @@ fn main() {
         
 
         
-        let mut samples = [std::ptr::null_mut() as *mut dds_builtintopic_endpoint; 2];
-        let mut info = [dds_sample_info_t::default(); 2];
         //assert!(reader > 0);
 
         let listener = DdsListener::new().on_data_available(move |entity|{
 
             println!("Callback received");
 
+            // the callback runs on a Cyclone thread, the buffers are local to each call
+            let mut samples = [std::ptr::null_mut() as *mut dds_builtintopic_endpoint; 2];
+            let mut info = [dds_sample_info_t::default(); 2];
+
             let ret = dds_take(
                 entity.entity(),
                 samples.as_mut_ptr() as *mut *mut c_void,
@@ -14,7 +14,16 @@
     limitations under the License.
 */
 
-use cyclonedds_sys::DdsEntity;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use cyclonedds_sys::{
+    dds_create_guardcondition, dds_delete, dds_entity_t, dds_set_guardcondition,
+    dds_waitset_attach, DDSError, DdsEntity,
+};
 
 /// An entity on which you can attach a DdsWriter
 pub trait DdsWritable {
@@ -29,3 +38,185 @@ pub trait DdsReadable {
 pub trait Entity {
     fn entity(&self) -> &DdsEntity;
 }
+
+type BlockingCall<R> = Box<dyn FnOnce(&Cancel) -> R + Send>;
+
+/// Future for a blocking call into Cyclone. The call runs on a helper thread
+/// that wakes the task when it completes. Dropping the future cancels the call,
+/// which ends its waits early through the `Cancel` it is given.
+pub(crate) struct BlockingFuture<R> {
+    call: Option<BlockingCall<R>>,
+    state: Arc<Mutex<(Option<Waker>, Option<R>)>>,
+    cancel: Arc<Cancel>,
+}
+
+impl<R> BlockingFuture<R>
+where
+    R: Send + 'static,
+{
+    pub(crate) fn new<F>(call: F) -> Self
+    where
+        F: FnOnce(&Cancel) -> R + Send + 'static,
+    {
+        Self {
+            call: Some(Box::new(call)),
+            state: Arc::new(Mutex::new((None, None))),
+            cancel: Arc::new(Cancel::new()),
+        }
+    }
+}
+
+impl<R> Future for BlockingFuture<R>
+where
+    R: Send + 'static,
+{
+    type Output = R;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(result) = state.1.take() {
+            return Poll::Ready(result);
+        }
+        state.0.replace(ctx.waker().clone());
+        drop(state);
+
+        if let Some(call) = self.call.take() {
+            let thread_state = self.state.clone();
+            let cancel = self.cancel.clone();
+            std::thread::spawn(move || {
+                let result = call(&cancel);
+                let mut state = thread_state.lock().unwrap();
+                state.1 = Some(result);
+                if let Some(waker) = state.0.take() {
+                    waker.wake();
+                }
+            });
+        }
+        Poll::Pending
+    }
+}
+
+impl<R> Drop for BlockingFuture<R> {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// Tells the call of a `BlockingFuture` that the future was dropped
+pub(crate) struct Cancel {
+    // the cancelled flag and the guard conditions to trigger
+    state: Mutex<(bool, Vec<dds_entity_t>)>,
+    changed: Condvar,
+}
+
+impl Cancel {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new((false, Vec::new())),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn cancel(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.0 = true;
+        for guard in &state.1 {
+            unsafe { dds_set_guardcondition(*guard, true) };
+        }
+        self.changed.notify_all();
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.state.lock().unwrap().0
+    }
+
+    /// Sleep for `duration` or until the future is dropped. Returns true if
+    /// the future was dropped.
+    pub(crate) fn sleep(&self, duration: Duration) -> bool {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self
+            .changed
+            .wait_timeout_while(state, duration, |state| !state.0)
+            .unwrap();
+        state.0
+    }
+
+    /// Attach a guard condition to a waitset, so that a wait on the waitset
+    /// returns when the future is dropped. The guard condition is created on
+    /// `participant` and deleted with the returned `CancelGuard`.
+    pub(crate) fn attach(
+        &self,
+        waitset: dds_entity_t,
+        participant: dds_entity_t,
+    ) -> Result<CancelGuard<'_>, DDSError> {
+        let mut state = self.state.lock().unwrap();
+        unsafe {
+            let guard = dds_create_guardcondition(participant);
+            if guard < 0 {
+                return Err(DDSError::from(guard));
+            }
+            dds_set_guardcondition(guard, state.0);
+            let ret = dds_waitset_attach(waitset, guard, 0);
+            if ret != 0 {
+                dds_delete(guard);
+                return Err(DDSError::from(ret));
+            }
+            state.1.push(guard);
+            Ok(CancelGuard {
+                cancel: self,
+                guard,
+            })
+        }
+    }
+}
+
+/// A guard condition triggered when a `BlockingFuture` is dropped
+pub(crate) struct CancelGuard<'a> {
+    cancel: &'a Cancel,
+    guard: dds_entity_t,
+}
+
+impl<'a> Drop for CancelGuard<'a> {
+    fn drop(&mut self) {
+        // not triggered any more once deleted
+        let mut state = self.cancel.state.lock().unwrap();
+        state.1.retain(|guard| *guard != self.guard);
+        unsafe { dds_delete(self.guard) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::DdsParticipant;
+    use cyclonedds_sys::{dds_create_waitset, dds_waitset_wait};
+    use std::sync::mpsc;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_dropped_future_cancels_wait() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let participant = unsafe { Entity::entity(&participant).entity() };
+        let (done, finished) = mpsc::channel();
+
+        let future = BlockingFuture::new(move |cancel: &Cancel| {
+            let waitset = unsafe { dds_create_waitset(participant) };
+            let guard = cancel.attach(waitset, participant).unwrap();
+            let woken =
+                unsafe { dds_waitset_wait(waitset, std::ptr::null_mut(), 0, 10_000_000_000) };
+            drop(guard);
+            unsafe { dds_delete(waitset) };
+            done.send((woken, cancel.sleep(Duration::from_secs(10))))
+                .unwrap();
+        });
+
+        let rt = Runtime::new().unwrap();
+        let waited = rt.block_on(tokio::time::timeout(Duration::from_millis(100), future));
+        assert!(waited.is_err());
+
+        // the helper thread stops waiting once the future is dropped
+        let (woken, cancelled) = finished.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(woken, 1);
+        assert!(cancelled);
+    }
+}
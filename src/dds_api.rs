@@ -15,10 +15,21 @@
 */
 
 use std::convert::From;
+use std::ffi::CStr;
+use std::fmt;
+use std::marker::PhantomData;
 
 use crate::common::Entity;
+use crate::dds_qos::DdsQos;
+use crate::serdes::{InstanceState, SampleState, ViewState};
 pub use cyclonedds_sys::dds_error::DDSError;
-use cyclonedds_sys::DdsEntity;
+use cyclonedds_sys::{
+    dds_builtintopic_endpoint_t, dds_entity_t, dds_guid_t, dds_instance_handle_t,
+    dds_instance_state_DDS_IST_ALIVE, dds_instance_state_DDS_IST_NOT_ALIVE_DISPOSED,
+    dds_instance_state_DDS_IST_NOT_ALIVE_NO_WRITERS, dds_return_t,
+    dds_sample_state_DDS_SST_NOT_READ, dds_sample_state_DDS_SST_READ, dds_view_state_DDS_VST_NEW,
+    dds_view_state_DDS_VST_OLD, size_t, DdsEntity,
+};
 
 //use crate::dds_writer::DdsWriter;
 pub use cyclonedds_sys::dds_status_id;
@@ -44,7 +55,12 @@ pub use cyclonedds_sys::StateMask;
 pub struct DdsStatus(u32);
 
 impl DdsStatus {
-    pub fn set(mut self, id: dds_status_id) -> Self {
+    /// A mask with no status set
+    pub const fn new() -> Self {
+        DdsStatus(0)
+    }
+
+    pub const fn set(mut self, id: dds_status_id) -> Self {
         let mask = 1 << id;
         self.0 |= mask;
         self
@@ -109,6 +125,302 @@ pub fn dds_triggered(entity: &dyn Entity) -> Result<(), DDSError> {
     }
 }
 
+/// Selects samples by their sample, view and instance state when reading or
+/// taking. A state category that is not restricted matches any state, so
+/// `StateFilter::not_read().alive()` selects unread samples of alive instances.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StateFilter {
+    sample: u32,
+    view: u32,
+    instance: u32,
+}
+
+impl StateFilter {
+    /// Match samples in any state
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    /// Match samples that have not been read before
+    pub fn not_read() -> Self {
+        Self::any().sample_state(SampleState::NotRead)
+    }
+
+    /// Match samples that have already been read
+    pub fn read() -> Self {
+        Self::any().sample_state(SampleState::Read)
+    }
+
+    /// Also match samples in the given sample state
+    pub fn sample_state(mut self, state: SampleState) -> Self {
+        self.sample |= match state {
+            SampleState::Read => dds_sample_state_DDS_SST_READ,
+            SampleState::NotRead => dds_sample_state_DDS_SST_NOT_READ,
+        };
+        self
+    }
+
+    /// Also match samples in the given view state
+    pub fn view_state(mut self, state: ViewState) -> Self {
+        self.view |= match state {
+            ViewState::New => dds_view_state_DDS_VST_NEW,
+            ViewState::Old => dds_view_state_DDS_VST_OLD,
+        };
+        self
+    }
+
+    /// Also match samples of instances in the given instance state
+    pub fn instance_state(mut self, state: InstanceState) -> Self {
+        self.instance |= match state {
+            InstanceState::Alive => dds_instance_state_DDS_IST_ALIVE,
+            InstanceState::Disposed => dds_instance_state_DDS_IST_NOT_ALIVE_DISPOSED,
+            InstanceState::NoWriters => dds_instance_state_DDS_IST_NOT_ALIVE_NO_WRITERS,
+        };
+        self
+    }
+
+    /// Match samples of instances seen for the first time
+    pub fn new_view(self) -> Self {
+        self.view_state(ViewState::New)
+    }
+
+    /// Match samples of instances that were seen before
+    pub fn not_new_view(self) -> Self {
+        self.view_state(ViewState::Old)
+    }
+
+    /// Match samples of alive instances
+    pub fn alive(self) -> Self {
+        self.instance_state(InstanceState::Alive)
+    }
+
+    /// Match samples of disposed instances
+    pub fn disposed(self) -> Self {
+        self.instance_state(InstanceState::Disposed)
+    }
+
+    /// Match samples of instances without writers
+    pub fn no_writers(self) -> Self {
+        self.instance_state(InstanceState::NoWriters)
+    }
+
+    /// Match samples of instances that are disposed or have no writers
+    pub fn not_alive(self) -> Self {
+        self.disposed().no_writers()
+    }
+
+    /// The state mask as used by the Cyclone read and take functions
+    pub fn mask(&self) -> u32 {
+        let any_sample = dds_sample_state_DDS_SST_READ | dds_sample_state_DDS_SST_NOT_READ;
+        let any_view = dds_view_state_DDS_VST_NEW | dds_view_state_DDS_VST_OLD;
+        let any_instance = dds_instance_state_DDS_IST_ALIVE
+            | dds_instance_state_DDS_IST_NOT_ALIVE_DISPOSED
+            | dds_instance_state_DDS_IST_NOT_ALIVE_NO_WRITERS;
+
+        let or_any = |states, any| if states == 0 { any } else { states };
+        or_any(self.sample, any_sample)
+            | or_any(self.view, any_view)
+            | or_any(self.instance, any_instance)
+    }
+}
+
+/// A coherent set on a publisher or subscriber, started by `begin_coherent`.
+/// The set is ended by calling `commit`, or when the guard is dropped.
+pub struct CoherentSet<'a> {
+    entity: DdsEntity,
+    ended: bool,
+    _owner: PhantomData<&'a DdsEntity>,
+}
+
+impl<'a> CoherentSet<'a> {
+    pub(crate) fn begin(entity: &'a DdsEntity) -> Result<Self, DDSError> {
+        let ret = unsafe { cyclonedds_sys::dds_begin_coherent(entity.entity()) };
+        if ret == 0 {
+            Ok(CoherentSet {
+                entity: entity.clone(),
+                ended: false,
+                _owner: PhantomData,
+            })
+        } else {
+            Err(DDSError::from(ret))
+        }
+    }
+
+    /// End the coherent set. On a publisher, this makes all the changes
+    /// of the set available to the readers at once.
+    pub fn commit(mut self) -> Result<(), DDSError> {
+        self.end()
+    }
+
+    fn end(&mut self) -> Result<(), DDSError> {
+        self.ended = true;
+        let ret = unsafe { cyclonedds_sys::dds_end_coherent(self.entity.entity()) };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(DDSError::from(ret))
+        }
+    }
+}
+
+impl<'a> Drop for CoherentSet<'a> {
+    fn drop(&mut self) {
+        if !self.ended {
+            if let Err(e) = self.end() {
+                println!("Ignoring dds_end_coherent failure: {}", e);
+            }
+        }
+    }
+}
+
+/// Handle of an instance or of a discovered remote entity. Handles are
+/// local to the participant that produced them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InstanceHandle(dds_instance_handle_t);
+
+impl InstanceHandle {
+    /// The handle that refers to no instance
+    pub const NIL: InstanceHandle = InstanceHandle(0);
+
+    pub fn is_nil(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl From<dds_instance_handle_t> for InstanceHandle {
+    fn from(handle: dds_instance_handle_t) -> Self {
+        InstanceHandle(handle)
+    }
+}
+
+impl From<InstanceHandle> for dds_instance_handle_t {
+    fn from(handle: InstanceHandle) -> Self {
+        handle.0
+    }
+}
+
+/// Globally unique identifier of a DDS entity
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Guid(pub [u8; 16]);
+
+impl From<dds_guid_t> for Guid {
+    fn from(guid: dds_guid_t) -> Self {
+        Guid(guid.v)
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, chunk) in self.0.chunks(4).enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            for b in chunk {
+                write!(f, "{:02x}", b)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A remote reader or writer that has been matched with a local writer or reader
+#[derive(Debug, Clone)]
+pub struct MatchedEndpoint {
+    /// The handle of the remote endpoint
+    pub handle: InstanceHandle,
+    pub guid: Guid,
+    pub participant_guid: Guid,
+    pub participant_handle: InstanceHandle,
+    pub topic_name: String,
+    pub type_name: String,
+    pub qos: DdsQos,
+    /// The partitions of the endpoint, from its Qos
+    pub partitions: Vec<String>,
+    /// The ownership strength of a writer, from its Qos
+    pub ownership_strength: Option<i32>,
+}
+
+impl MatchedEndpoint {
+    /// Convert the endpoint data returned by Cyclone. The endpoint is freed.
+    unsafe fn from_raw(handle: InstanceHandle, endpoint: *mut dds_builtintopic_endpoint_t) -> Self {
+        let ep = &mut *endpoint;
+        let to_string = |name: *mut std::os::raw::c_char| {
+            if name.is_null() {
+                String::new()
+            } else {
+                let s = CStr::from_ptr(name).to_string_lossy().into_owned();
+                cyclonedds_sys::dds_free(name as *mut std::ffi::c_void);
+                s
+            }
+        };
+
+        // the Qos is now owned by the DdsQos
+        let qos = DdsQos::from_raw(ep.qos);
+        let matched = MatchedEndpoint {
+            handle,
+            guid: ep.key.into(),
+            participant_guid: ep.participant_key.into(),
+            participant_handle: ep.participant_instance_handle.into(),
+            topic_name: to_string(ep.topic_name),
+            type_name: to_string(ep.type_name),
+            partitions: qos.partitions(),
+            ownership_strength: qos.ownership_strength(),
+            qos,
+        };
+        cyclonedds_sys::dds_free(endpoint as *mut std::ffi::c_void);
+        matched
+    }
+}
+
+/// Get the endpoints matched with a reader or writer. `get_handles` and `get_data`
+/// are the Cyclone functions for the matched publications or subscriptions.
+pub(crate) fn matched_endpoints(
+    entity: &DdsEntity,
+    get_handles: unsafe extern "C" fn(
+        dds_entity_t,
+        *mut dds_instance_handle_t,
+        size_t,
+    ) -> dds_return_t,
+    get_data: unsafe extern "C" fn(
+        dds_entity_t,
+        dds_instance_handle_t,
+    ) -> *mut dds_builtintopic_endpoint_t,
+) -> Result<Vec<MatchedEndpoint>, DDSError> {
+    let mut handles: Vec<dds_instance_handle_t> = Vec::new();
+    // the number of matches can change between calls, retry until the buffer is large enough
+    loop {
+        let ret = unsafe {
+            get_handles(
+                entity.entity(),
+                handles.as_mut_ptr(),
+                handles.capacity() as size_t,
+            )
+        };
+        if ret < 0 {
+            return Err(DDSError::from(ret));
+        }
+        let count = ret as usize;
+        if count <= handles.capacity() {
+            unsafe { handles.set_len(count) };
+            break;
+        }
+        handles.reserve(count);
+    }
+
+    Ok(handles
+        .into_iter()
+        .filter_map(|handle| {
+            let endpoint = unsafe { get_data(entity.entity(), handle) };
+            if endpoint.is_null() {
+                // the remote endpoint disappeared in the meantime
+                None
+            } else {
+                Some(unsafe { MatchedEndpoint::from_raw(handle.into(), endpoint) })
+            }
+        })
+        .collect())
+}
+
 #[cfg(test)]
 mod dds_qos_tests {
     use super::*;
@@ -126,4 +438,31 @@ mod dds_qos_tests {
         assert_eq!(true, status.is_set(DDS_SUBSCRIPTION_MATCHED_STATUS_ID));
         assert_eq!(false, status.is_set(DDS_SAMPLE_REJECTED_STATUS_ID));
     }
+
+    #[test]
+    fn test_guid_display() {
+        let guid = Guid([
+            0x01, 0x10, 0xab, 0xcd, 0, 0, 0, 1, 0xff, 0xee, 0xdd, 0xcc, 0, 0, 0x1, 0x07,
+        ]);
+        assert_eq!(guid.to_string(), "0110abcd:00000001:ffeeddcc:00000107");
+    }
+
+    #[test]
+    fn test_state_filter_mask() {
+        assert_eq!(
+            StateFilter::any().mask(),
+            *StateMask::from(State::DdsAnyState)
+        );
+        assert_eq!(
+            StateFilter::not_read().alive().mask(),
+            *(State::DdsNotReadSampleState | State::DdsAnyViewState | State::DdsAliveInstanceState)
+        );
+        assert_eq!(
+            StateFilter::read().new_view().not_alive().mask(),
+            *(State::DdsReadSampleState
+                | State::DdsNewViewState
+                | State::DdsNotAliveDisposedInstanceState
+                | State::DdsNotAliveNoWritersInstanceState)
+        );
+    }
 }
@@ -0,0 +1,398 @@
+/*
+    Copyright 2021 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Instance lifecycle events for readers of keyed topics.
+//!
+//! The samples of the reader are taken and turned into one event per sample,
+//! plus an event whenever an instance is disposed or loses its writers. The
+//! key of an instance that is disposed or loses its writers comes from the
+//! sample that notifies it, so these events are also produced for instances
+//! for which no valid sample was ever received.
+
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use cyclonedds_sys::*;
+use futures_core::Stream;
+
+use crate::dds_api::InstanceHandle;
+use crate::dds_reader::{DdsReader, ReaderWaker};
+use crate::error::ReaderError;
+use crate::serdes::{InstanceState, SampleBuffer, TopicType, ViewState};
+use crate::Entity;
+
+/// Number of samples taken from the reader at a time
+const INSTANCE_EVENT_BATCH: usize = 16;
+
+/// A change in the life of an instance
+pub enum InstanceEvent<T: TopicType> {
+    /// First sample of an instance that is new to the reader (`ViewState::New`),
+    /// either never seen before or seen again after it was disposed or lost its
+    /// writers
+    New(T::Key, T),
+    /// Another sample of an alive instance
+    Updated(T::Key, T),
+    /// The instance was disposed by a writer
+    Disposed(T::Key),
+    /// All writers of the instance are gone
+    NoWriters(T::Key),
+}
+
+impl<T: TopicType> InstanceEvent<T> {
+    /// The key of the instance
+    pub fn key(&self) -> &T::Key {
+        match self {
+            InstanceEvent::New(key, _)
+            | InstanceEvent::Updated(key, _)
+            | InstanceEvent::Disposed(key)
+            | InstanceEvent::NoWriters(key) => key,
+        }
+    }
+}
+
+// Takes samples from the reader and keeps track of the alive instances
+struct InstanceTracker<T: TopicType> {
+    buffer: SampleBuffer<T>,
+    events: VecDeque<InstanceEvent<T>>,
+    instances: HashMap<InstanceHandle, T::Key>,
+}
+
+impl<T> InstanceTracker<T>
+where
+    T: TopicType + Clone,
+{
+    fn new() -> Self {
+        Self {
+            buffer: SampleBuffer::new(INSTANCE_EVENT_BATCH),
+            events: VecDeque::new(),
+            instances: HashMap::new(),
+        }
+    }
+
+    // take the available samples and queue their events
+    fn take(&mut self, entity: &DdsEntity) -> Result<(), DDSError> {
+        let taken = DdsReader::<T>::readn_from_entity_now(entity, &mut self.buffer, true)?;
+
+        for index in 0..taken.len() {
+            let info = self.buffer.info(index);
+            let handle = info.instance_handle();
+            let sample = self.buffer.get(index);
+            let key = sample.key();
+            if let (Some(sample), Some(key)) = (sample.try_deref(), key.clone()) {
+                let known = self.instances.insert(handle, key.clone()).is_some();
+                // the view state is also new for the later samples of a new
+                // instance in the same batch
+                if info.view_state() == ViewState::New && !known {
+                    self.events
+                        .push_back(InstanceEvent::New(key, sample.clone()));
+                } else {
+                    self.events
+                        .push_back(InstanceEvent::Updated(key, sample.clone()));
+                }
+                // a sample rank of 0 marks the last sample of the instance in this batch
+                if info.sample_rank() != 0 {
+                    continue;
+                }
+            }
+
+            // the tracker has not seen the instance if it never had valid samples
+            let event = match info.instance_state() {
+                InstanceState::Alive => None,
+                InstanceState::Disposed => self
+                    .instances
+                    .remove(&handle)
+                    .or(key)
+                    .map(InstanceEvent::Disposed),
+                InstanceState::NoWriters => self
+                    .instances
+                    .remove(&handle)
+                    .or(key)
+                    .map(InstanceEvent::NoWriters),
+            };
+            if let Some(event) = event {
+                self.events.push_back(event);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Blocking iterator over the instance events of a reader. Created by
+/// `DdsReader::instance_events`.
+pub struct InstanceEvents<'a, T: TopicType> {
+    reader: &'a DdsReader<T>,
+    tracker: InstanceTracker<T>,
+    readcondition: dds_entity_t,
+    waitset: dds_entity_t,
+}
+
+impl<'a, T> InstanceEvents<'a, T>
+where
+    T: TopicType + Clone,
+{
+    pub(crate) fn new(reader: &'a DdsReader<T>) -> Result<Self, DDSError> {
+        unsafe {
+            let entity = reader.entity().entity();
+            let readcondition =
+                dds_create_readcondition(entity, *StateMask::from(State::DdsAnyState));
+            if readcondition < 0 {
+                return Err(DDSError::from(readcondition));
+            }
+            let waitset = dds_create_waitset(dds_get_participant(entity));
+            if waitset < 0 {
+                dds_delete(readcondition);
+                return Err(DDSError::from(waitset));
+            }
+            let events = Self {
+                reader,
+                tracker: InstanceTracker::new(),
+                readcondition,
+                waitset,
+            };
+            let ret = dds_waitset_attach(waitset, readcondition, 0);
+            if ret == 0 {
+                Ok(events)
+            } else {
+                Err(DDSError::from(ret))
+            }
+        }
+    }
+}
+
+impl<'a, T> Iterator for InstanceEvents<'a, T>
+where
+    T: TopicType + Clone,
+{
+    type Item = InstanceEvent<T>;
+
+    /// Wait for the next event. Returns None if the reader can no longer be read or waited on.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.tracker.events.pop_front() {
+                return Some(event);
+            }
+            if self.tracker.take(self.reader.entity()).is_err() {
+                return None;
+            }
+            if !self.tracker.events.is_empty() {
+                continue;
+            }
+            let ret = unsafe { dds_waitset_wait(self.waitset, std::ptr::null_mut(), 0, i64::MAX) };
+            if ret < 0 {
+                return None;
+            }
+        }
+    }
+}
+
+impl<'a, T: TopicType> Drop for InstanceEvents<'a, T> {
+    fn drop(&mut self) {
+        unsafe {
+            dds_delete(self.waitset);
+            dds_delete(self.readcondition);
+        }
+    }
+}
+
+/// Stream of the instance events of an async reader. Created by
+/// `DdsReader::instance_event_stream`. A failure to take the samples of the
+/// reader is returned as an `Err` item.
+pub struct InstanceEventStream<'a, T: TopicType> {
+    reader: &'a DdsReader<T>,
+    waker: ReaderWaker,
+    tracker: InstanceTracker<T>,
+}
+
+impl<'a, T> InstanceEventStream<'a, T>
+where
+    T: TopicType + Clone,
+{
+    pub(crate) fn new(reader: &'a DdsReader<T>, waker: ReaderWaker) -> Self {
+        Self {
+            reader,
+            waker,
+            tracker: InstanceTracker::new(),
+        }
+    }
+}
+
+// The stream is never pinned structurally
+impl<'a, T: TopicType> Unpin for InstanceEventStream<'a, T> {}
+
+impl<'a, T> Stream for InstanceEventStream<'a, T>
+where
+    T: TopicType + Clone,
+{
+    type Item = Result<InstanceEvent<T>, ReaderError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if let Some(event) = this.tracker.events.pop_front() {
+            return Poll::Ready(Some(Ok(event)));
+        }
+
+        // Lock the waker first so a data available callback cannot be missed
+        let mut waker = this.waker.lock().unwrap();
+
+        if let Err(e) = this.tracker.take(this.reader.entity()) {
+            return Poll::Ready(Some(Err(ReaderError::DdsError(e))));
+        }
+        match this.tracker.events.pop_front() {
+            Some(event) => Poll::Ready(Some(Ok(event))),
+            None => {
+                waker.register(ctx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        DdsListener, DdsParticipant, DdsPublisher, DdsQos, DdsSubscriber, DdsTopic, DdsWriter,
+    };
+    use cdds_derive::Topic;
+    use futures::StreamExt;
+    use serde_derive::{Deserialize, Serialize};
+    use std::sync::Arc;
+    use tokio::runtime::Runtime;
+
+    #[derive(Serialize, Deserialize, Topic, Debug, Clone, PartialEq, Default)]
+    struct Sensor {
+        #[topic_key]
+        id: u32,
+        value: u32,
+    }
+
+    fn describe(event: &InstanceEvent<Sensor>) -> (&'static str, u32, Option<u32>) {
+        match event {
+            InstanceEvent::New(key, sample) => ("new", key.id, Some(sample.value)),
+            InstanceEvent::Updated(key, sample) => ("updated", key.id, Some(sample.value)),
+            InstanceEvent::Disposed(key) => ("disposed", key.id, None),
+            InstanceEvent::NoWriters(key) => ("no_writers", key.id, None),
+        }
+    }
+
+    fn sensor(id: u32, value: u32) -> Arc<Sensor> {
+        Arc::new(Sensor { id, value })
+    }
+
+    #[test]
+    fn test_instance_events() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Sensor::create_topic(&participant, Some("instance_events"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let mut qos = DdsQos::create().unwrap();
+        qos.set_history(dds_history_kind::DDS_HISTORY_KEEP_LAST, 10);
+        let reader = DdsReader::create(&subscriber, topic, Some(qos), None).unwrap();
+
+        writer.write(sensor(1, 10)).unwrap();
+        writer.write(sensor(1, 11)).unwrap();
+        writer.write(sensor(2, 20)).unwrap();
+        // deleting the writer disposes its instances
+        drop(writer);
+
+        let events: Vec<_> = reader
+            .instance_events()
+            .unwrap()
+            .take(5)
+            .map(|event| describe(&event))
+            .collect();
+        let of_instance = |id| {
+            events
+                .iter()
+                .filter(|event| event.1 == id)
+                .map(|event| (event.0, event.2))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            of_instance(1),
+            vec![("new", Some(10)), ("updated", Some(11)), ("disposed", None)]
+        );
+        assert_eq!(of_instance(2), vec![("new", Some(20)), ("disposed", None)]);
+    }
+
+    #[test]
+    fn test_instance_events_of_unseen_instances() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Sensor::create_topic(&participant, Some("unseen_instances"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = DdsReader::create(&subscriber, topic, None, None).unwrap();
+
+        // the samples are taken before the tracker sees them
+        writer.write(sensor(4, 40)).unwrap();
+        writer.write(sensor(5, 50)).unwrap();
+        let mut samples = Sensor::create_sample_buffer(4);
+        assert_eq!(reader.take_now(&mut samples).unwrap().valid, 2);
+
+        // the instance is not new to the reader any more
+        writer.write(sensor(4, 41)).unwrap();
+        drop(writer);
+
+        let mut events: Vec<_> = reader
+            .instance_events()
+            .unwrap()
+            .take(3)
+            .map(|event| describe(&event))
+            .collect();
+        events.sort_by_key(|event| event.1);
+        assert_eq!(
+            events,
+            vec![
+                ("updated", 4, Some(41)),
+                ("disposed", 4, None),
+                ("disposed", 5, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_instance_event_stream() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Sensor::create_topic(&participant, Some("instance_event_stream"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = DdsReader::create_async(&subscriber, topic, None).unwrap();
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut events = reader.instance_event_stream().unwrap();
+
+            writer.write(sensor(3, 30)).unwrap();
+            let event = events.next().await.unwrap().unwrap();
+            assert_eq!(describe(&event), ("new", 3, Some(30)));
+
+            writer.write(sensor(3, 31)).unwrap();
+            let event = events.next().await.unwrap().unwrap();
+            assert_eq!(describe(&event), ("updated", 3, Some(31)));
+
+            drop(writer);
+            let event = events.next().await.unwrap().unwrap();
+            assert_eq!(describe(&event), ("disposed", 3, None));
+        });
+    }
+}
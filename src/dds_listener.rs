@@ -28,43 +28,98 @@
 //! }).
 //! hook(); // The hook call will finalize the listener. No more callbacks can be attached after this.
 //! ```
-
+//! The status callbacks receive the same typed statuses as the status getters
+//! and status streams of the readers and writers.
+//! # Threading
+//! Cyclone invokes the callbacks from its own threads, usually the thread that
+//! received the data or the thread of the application that caused the status
+//! change, such as a local write. The callbacks must therefore be `Send`. A
+//! listener attached to several entities can have the same callback invoked for
+//! the entities at the same time, so each callback is called under a lock of its
+//! own and may keep mutable state. A callback must not block for long, it holds
+//! up the thread delivering data, and must not cause itself to be invoked again
+//! on the same thread, as by writing a sample its own reader receives.
+//!
+//! A panic in a callback is caught before it reaches Cyclone. The listener keeps
+//! being called and the message of the last panic can be read back with
+//! `DdsListener::take_panic`.
+
+use crate::dds_status::*;
 use cyclonedds_sys::dds_listener_t;
 use cyclonedds_sys::*;
+use std::any::Any;
 use std::convert::From;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, PoisonError};
 
 /*
  Each listener has its own set of callbacks.
 */
 
+// Call a callback from a trampoline. Cyclone may invoke a callback from several
+// threads at once, so the calls are serialized by the lock of the callback. A
+// panic must not unwind into Cyclone, so it is caught and the trampoline
+// returns normally. Its message is kept for `DdsListener::take_panic`.
+fn call_guarded<F: ?Sized>(
+    callbacks: &Callbacks,
+    name: &str,
+    callback: &Mutex<Box<F>>,
+    call: impl FnOnce(&mut F),
+) {
+    // the panic is caught while the lock is held, so the lock is never poisoned
+    let mut callback = callback.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| call(&mut **callback))) {
+        let message = format!(
+            "panic in the {} callback: {}",
+            name,
+            panic_message(payload.as_ref())
+        );
+        *callbacks
+            .panic
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(message);
+    }
+}
+
+// the message of a panic raised with a string, as by `panic!` and `unwrap`
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown payload"
+    }
+}
+
+// A callback with the entity it is invoked for
+type EntityCallback = Mutex<Box<dyn FnMut(DdsEntity) + Send + 'static>>;
+// A callback with the entity and the status that changed
+type StatusCallback<S> = Mutex<Box<dyn FnMut(DdsEntity, S) + Send + 'static>>;
+
 /// The callbacks are in a different structure that is always
 /// heap allocated.
 struct Callbacks {
     // Callbacks for readers
-    on_sample_lost: Option<Box<dyn FnMut(DdsEntity, dds_sample_lost_status_t) + 'static>>,
-    on_data_available: Option<Box<dyn FnMut(DdsEntity) + 'static>>,
-    on_sample_rejected: Option<Box<dyn FnMut(DdsEntity, dds_sample_rejected_status_t) + 'static>>,
-    on_liveliness_changed:
-        Option<Box<dyn FnMut(DdsEntity, dds_liveliness_changed_status_t) + 'static>>,
-    on_requested_deadline_missed:
-        Option<Box<dyn FnMut(DdsEntity, dds_requested_deadline_missed_status_t) + 'static>>,
-    on_requested_incompatible_qos:
-        Option<Box<dyn FnMut(DdsEntity, dds_requested_incompatible_qos_status_t) + 'static>>,
-    on_subscription_matched:
-        Option<Box<dyn FnMut(DdsEntity, dds_subscription_matched_status_t) + 'static>>,
+    on_sample_lost: Option<StatusCallback<SampleLostStatus>>,
+    on_data_available: Option<EntityCallback>,
+    on_sample_rejected: Option<StatusCallback<SampleRejectedStatus>>,
+    on_liveliness_changed: Option<StatusCallback<LivelinessChangedStatus>>,
+    on_requested_deadline_missed: Option<StatusCallback<RequestedDeadlineMissedStatus>>,
+    on_requested_incompatible_qos: Option<StatusCallback<RequestedIncompatibleQosStatus>>,
+    on_subscription_matched: Option<StatusCallback<SubscriptionMatchedStatus>>,
 
     //callbacks for writers
-    on_liveliness_lost: Option<Box<dyn FnMut(DdsEntity, dds_liveliness_lost_status_t) + 'static>>,
-    on_offered_deadline_missed:
-        Option<Box<dyn FnMut(DdsEntity, dds_offered_deadline_missed_status_t) + 'static>>,
-    on_offered_incompatible_qos:
-        Option<Box<dyn FnMut(DdsEntity, dds_offered_incompatible_qos_status_t) + 'static>>,
-    on_publication_matched:
-        Option<Box<dyn FnMut(DdsEntity, dds_publication_matched_status_t) + 'static>>,
-
-    on_inconsistent_topic:
-        Option<Box<dyn FnMut(DdsEntity, dds_inconsistent_topic_status_t) + 'static>>,
-    on_data_on_readers: Option<Box<dyn FnMut(DdsEntity) + 'static>>,
+    on_liveliness_lost: Option<StatusCallback<LivelinessLostStatus>>,
+    on_offered_deadline_missed: Option<StatusCallback<OfferedDeadlineMissedStatus>>,
+    on_offered_incompatible_qos: Option<StatusCallback<OfferedIncompatibleQosStatus>>,
+    on_publication_matched: Option<StatusCallback<PublicationMatchedStatus>>,
+
+    on_inconsistent_topic: Option<StatusCallback<InconsistentTopicStatus>>,
+    on_data_on_readers: Option<EntityCallback>,
+
+    // the last panic caught in one of the callbacks
+    panic: Mutex<Option<String>>,
 }
 
 impl<'a> Default for Callbacks {
@@ -83,10 +138,13 @@ impl<'a> Default for Callbacks {
             on_publication_matched: None,
             on_inconsistent_topic: None,
             on_data_on_readers: None,
+            panic: Mutex::new(None),
         }
     }
 }
 
+// The raw pointers are only used under the lock of DdsListener, and the
+// callbacks they point to are Send and called under their own lock.
 unsafe impl Send for Inner {}
 struct Inner {
     listener: Option<*mut dds_listener_t>,
@@ -117,6 +175,27 @@ impl<'a> Default for DdsListener {
     }
 }
 
+impl DdsListener {
+    /// The message of the last panic caught in a callback of this listener, if
+    /// any. Reading the message clears it. Keep a clone of the listener to
+    /// read this once the listener is handed to an entity; the clones share
+    /// the callbacks.
+    pub fn take_panic(&self) -> Option<String> {
+        let inner = self.inner.lock().unwrap();
+        let callbacks = match (inner.raw_ptr, &inner.callbacks) {
+            (Some(raw), _) => unsafe { &*raw },
+            (None, Some(callbacks)) => callbacks,
+            (None, None) => return None,
+        };
+        let message = callbacks
+            .panic
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        message
+    }
+}
+
 impl<'a> From<&DdsListener> for *const dds_listener_t {
     fn from(listener: &DdsListener) -> Self {
         if let Some(listener) = listener.inner.lock().unwrap().listener {
@@ -233,10 +312,10 @@ impl<'a> DdsListener {
 impl DdsListener {
     pub fn on_data_available<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity) + 'static,
+        F: FnMut(DdsEntity) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_data_available = Some(Box::new(callback));
+            callbacks.on_data_available = Some(Mutex::new(Box::new(callback)));
         }
 
         self
@@ -246,11 +325,12 @@ impl DdsListener {
         reader: dds_entity_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //        println!("C Callback!");
-        if let Some(avail) = &mut callbacks.on_data_available {
-            avail(DdsEntity::new(reader));
+        if let Some(avail) = &callbacks.on_data_available {
+            call_guarded(callbacks, "on_data_available", avail, |avail| {
+                avail(DdsEntity::new(reader))
+            });
         }
     }
 }
@@ -259,10 +339,10 @@ impl<'a> DdsListener {
     /////
     pub fn on_sample_lost<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity, dds_sample_lost_status_t) + 'static,
+        F: FnMut(DdsEntity, SampleLostStatus) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_sample_lost = Some(Box::new(callback));
+            callbacks.on_sample_lost = Some(Mutex::new(Box::new(callback)));
         }
         self
     }
@@ -272,11 +352,12 @@ impl<'a> DdsListener {
         status: dds_sample_lost_status_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //println!("C Callback - sample lost");
-        if let Some(lost) = &mut callbacks.on_sample_lost {
-            lost(DdsEntity::new(reader), status);
+        if let Some(lost) = &callbacks.on_sample_lost {
+            call_guarded(callbacks, "on_sample_lost", lost, |lost| {
+                lost(DdsEntity::new(reader), status.into())
+            });
         }
     }
 }
@@ -285,10 +366,10 @@ impl<'a> DdsListener {
     //////
     pub fn on_sample_rejected<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity, dds_sample_rejected_status_t) + 'static,
+        F: FnMut(DdsEntity, SampleRejectedStatus) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_sample_rejected = Some(Box::new(callback));
+            callbacks.on_sample_rejected = Some(Mutex::new(Box::new(callback)));
         }
         self
     }
@@ -298,11 +379,12 @@ impl<'a> DdsListener {
         status: dds_sample_rejected_status_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //println!("C Callback - sample rejected");
-        if let Some(rejected) = &mut callbacks.on_sample_rejected {
-            rejected(DdsEntity::new(reader), status);
+        if let Some(rejected) = &callbacks.on_sample_rejected {
+            call_guarded(callbacks, "on_sample_rejected", rejected, |rejected| {
+                rejected(DdsEntity::new(reader), status.into())
+            });
         }
     }
 }
@@ -311,10 +393,10 @@ impl<'a> DdsListener {
 impl<'a> DdsListener {
     pub fn on_liveliness_changed<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity, dds_liveliness_changed_status_t) + 'static,
+        F: FnMut(DdsEntity, LivelinessChangedStatus) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_liveliness_changed = Some(Box::new(callback));
+            callbacks.on_liveliness_changed = Some(Mutex::new(Box::new(callback)));
         }
         self
     }
@@ -324,11 +406,12 @@ impl<'a> DdsListener {
         status: dds_liveliness_changed_status_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //println!("C Callback - Liveliness changed");
-        if let Some(changed) = &mut callbacks.on_liveliness_changed {
-            changed(DdsEntity::new(entity), status);
+        if let Some(changed) = &callbacks.on_liveliness_changed {
+            call_guarded(callbacks, "on_liveliness_changed", changed, |changed| {
+                changed(DdsEntity::new(entity), status.into())
+            });
         }
     }
 }
@@ -336,10 +419,10 @@ impl<'a> DdsListener {
 impl<'a> DdsListener {
     pub fn on_requested_deadline_missed<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity, dds_requested_deadline_missed_status_t) + 'static,
+        F: FnMut(DdsEntity, RequestedDeadlineMissedStatus) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_requested_deadline_missed = Some(Box::new(callback));
+            callbacks.on_requested_deadline_missed = Some(Mutex::new(Box::new(callback)));
         }
         self
     }
@@ -349,11 +432,15 @@ impl<'a> DdsListener {
         status: dds_requested_deadline_missed_status_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //println!("C Callback - requested deadline missed");
-        if let Some(missed) = &mut callbacks.on_requested_deadline_missed {
-            missed(DdsEntity::new(entity), status);
+        if let Some(missed) = &callbacks.on_requested_deadline_missed {
+            call_guarded(
+                callbacks,
+                "on_requested_deadline_missed",
+                missed,
+                |missed| missed(DdsEntity::new(entity), status.into()),
+            );
         }
     }
 }
@@ -361,10 +448,10 @@ impl<'a> DdsListener {
 impl<'a> DdsListener {
     pub fn on_requested_incompatible_qos<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity, dds_requested_incompatible_qos_status_t) + 'static,
+        F: FnMut(DdsEntity, RequestedIncompatibleQosStatus) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_requested_incompatible_qos = Some(Box::new(callback));
+            callbacks.on_requested_incompatible_qos = Some(Mutex::new(Box::new(callback)));
         }
         self
     }
@@ -374,11 +461,15 @@ impl<'a> DdsListener {
         status: dds_requested_incompatible_qos_status_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //println!("C Callback - requested incompatible QOS");
-        if let Some(incompatible_qos) = &mut callbacks.on_requested_incompatible_qos {
-            incompatible_qos(DdsEntity::new(entity), status);
+        if let Some(incompatible_qos) = &callbacks.on_requested_incompatible_qos {
+            call_guarded(
+                callbacks,
+                "on_requested_incompatible_qos",
+                incompatible_qos,
+                |incompatible_qos| incompatible_qos(DdsEntity::new(entity), status.into()),
+            );
         }
     }
 }
@@ -386,10 +477,10 @@ impl<'a> DdsListener {
 impl<'a> DdsListener {
     pub fn on_subscription_matched<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity, dds_subscription_matched_status_t) + 'static,
+        F: FnMut(DdsEntity, SubscriptionMatchedStatus) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_subscription_matched = Some(Box::new(callback));
+            callbacks.on_subscription_matched = Some(Mutex::new(Box::new(callback)));
         }
         self
     }
@@ -399,11 +490,12 @@ impl<'a> DdsListener {
         status: dds_subscription_matched_status_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //println!("C Callback - subscription matched");
-        if let Some(matched) = &mut callbacks.on_subscription_matched {
-            matched(DdsEntity::new(entity), status);
+        if let Some(matched) = &callbacks.on_subscription_matched {
+            call_guarded(callbacks, "on_subscription_matched", matched, |matched| {
+                matched(DdsEntity::new(entity), status.into())
+            });
         }
     }
 }
@@ -411,10 +503,10 @@ impl<'a> DdsListener {
 impl<'a> DdsListener {
     pub fn on_liveliness_lost<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity, dds_liveliness_lost_status_t) + 'static,
+        F: FnMut(DdsEntity, LivelinessLostStatus) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_liveliness_lost = Some(Box::new(callback));
+            callbacks.on_liveliness_lost = Some(Mutex::new(Box::new(callback)));
         }
         self
     }
@@ -424,11 +516,12 @@ impl<'a> DdsListener {
         status: dds_liveliness_lost_status_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //println!("C Callback - liveliness lost");
-        if let Some(lost) = &mut callbacks.on_liveliness_lost {
-            lost(DdsEntity::new(entity), status);
+        if let Some(lost) = &callbacks.on_liveliness_lost {
+            call_guarded(callbacks, "on_liveliness_lost", lost, |lost| {
+                lost(DdsEntity::new(entity), status.into())
+            });
         }
     }
 }
@@ -436,10 +529,10 @@ impl<'a> DdsListener {
 impl<'a> DdsListener {
     pub fn on_offered_deadline_missed<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity, dds_offered_deadline_missed_status_t) + 'static,
+        F: FnMut(DdsEntity, OfferedDeadlineMissedStatus) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_offered_deadline_missed = Some(Box::new(callback));
+            callbacks.on_offered_deadline_missed = Some(Mutex::new(Box::new(callback)));
         }
         self
     }
@@ -449,11 +542,12 @@ impl<'a> DdsListener {
         status: dds_offered_deadline_missed_status_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //println!("C Callback - offered deadline missed");
-        if let Some(missed) = &mut callbacks.on_offered_deadline_missed {
-            missed(DdsEntity::new(entity), status);
+        if let Some(missed) = &callbacks.on_offered_deadline_missed {
+            call_guarded(callbacks, "on_offered_deadline_missed", missed, |missed| {
+                missed(DdsEntity::new(entity), status.into())
+            });
         }
     }
 }
@@ -461,10 +555,10 @@ impl<'a> DdsListener {
 impl<'a> DdsListener {
     pub fn on_offered_incompatible_qos<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity, dds_offered_incompatible_qos_status_t) + 'static,
+        F: FnMut(DdsEntity, OfferedIncompatibleQosStatus) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_offered_incompatible_qos = Some(Box::new(callback));
+            callbacks.on_offered_incompatible_qos = Some(Mutex::new(Box::new(callback)));
         }
         self
     }
@@ -474,11 +568,15 @@ impl<'a> DdsListener {
         status: dds_offered_incompatible_qos_status_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //println!("C Callback - offered incompatible QOS");
-        if let Some(incompatible) = &mut callbacks.on_offered_incompatible_qos {
-            incompatible(DdsEntity::new(entity), status);
+        if let Some(incompatible) = &callbacks.on_offered_incompatible_qos {
+            call_guarded(
+                callbacks,
+                "on_offered_incompatible_qos",
+                incompatible,
+                |incompatible| incompatible(DdsEntity::new(entity), status.into()),
+            );
         }
     }
 }
@@ -486,10 +584,10 @@ impl<'a> DdsListener {
 impl<'a> DdsListener {
     pub fn on_publication_matched<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity, dds_publication_matched_status_t) + 'static,
+        F: FnMut(DdsEntity, PublicationMatchedStatus) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_publication_matched = Some(Box::new(callback));
+            callbacks.on_publication_matched = Some(Mutex::new(Box::new(callback)));
         }
         self
     }
@@ -499,11 +597,12 @@ impl<'a> DdsListener {
         status: dds_publication_matched_status_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //println!("C Callback - publication matched");
-        if let Some(matched) = &mut callbacks.on_publication_matched {
-            matched(DdsEntity::new(entity), status);
+        if let Some(matched) = &callbacks.on_publication_matched {
+            call_guarded(callbacks, "on_publication_matched", matched, |matched| {
+                matched(DdsEntity::new(entity), status.into())
+            });
         }
     }
 }
@@ -511,10 +610,10 @@ impl<'a> DdsListener {
 impl<'a> DdsListener {
     pub fn on_inconsistent_topic<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity, dds_inconsistent_topic_status_t) + 'static,
+        F: FnMut(DdsEntity, InconsistentTopicStatus) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_inconsistent_topic = Some(Box::new(callback));
+            callbacks.on_inconsistent_topic = Some(Mutex::new(Box::new(callback)));
         }
         self
     }
@@ -524,11 +623,15 @@ impl<'a> DdsListener {
         status: dds_inconsistent_topic_status_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //println!("C Callback - inconsistent topic");
-        if let Some(inconsistant) = &mut callbacks.on_inconsistent_topic {
-            inconsistant(DdsEntity::new(entity), status);
+        if let Some(inconsistant) = &callbacks.on_inconsistent_topic {
+            call_guarded(
+                callbacks,
+                "on_inconsistent_topic",
+                inconsistant,
+                |inconsistant| inconsistant(DdsEntity::new(entity), status.into()),
+            );
         }
     }
 }
@@ -536,10 +639,10 @@ impl<'a> DdsListener {
 impl<'a> DdsListener {
     pub fn on_data_on_readers<F>(self, callback: F) -> Self
     where
-        F: FnMut(DdsEntity) + 'static,
+        F: FnMut(DdsEntity) + Send + 'static,
     {
         if let Some(callbacks) = &mut self.inner.lock().unwrap().callbacks {
-            callbacks.on_data_on_readers = Some(Box::new(callback));
+            callbacks.on_data_on_readers = Some(Mutex::new(Box::new(callback)));
         }
         self
     }
@@ -548,27 +651,29 @@ impl<'a> DdsListener {
         entity: dds_entity_t,
         data: *mut std::ffi::c_void,
     ) {
-        let callbacks_ptr = data as *mut Callbacks;
-        let callbacks = &mut *callbacks_ptr;
+        let callbacks = &*(data as *const Callbacks);
         //println!("C Callback - data on readers");
-        if let Some(data) = &mut callbacks.on_data_on_readers {
-            data(DdsEntity::new(entity));
+        if let Some(data) = &callbacks.on_data_on_readers {
+            call_guarded(callbacks, "on_data_on_readers", data, |data| {
+                data(DdsEntity::new(entity))
+            });
         }
     }
 }
 
-impl<'a> Drop for DdsListener {
+// The clones of a listener share Inner, which is dropped with the last clone
+impl Drop for Inner {
     fn drop(&mut self) {
         // delete the listener so we are sure of not
         // getting any callbacks
-        if let Some(listener) = &self.inner.lock().unwrap().listener {
+        if let Some(listener) = self.listener.take() {
             unsafe {
-                dds_reset_listener(*listener);
-                dds_delete_listener(*listener);
+                dds_reset_listener(listener);
+                dds_delete_listener(listener);
             }
         }
         // gain back control of the Callback structure
-        if let Some(raw) = self.inner.lock().unwrap().raw_ptr.take() {
+        if let Some(raw) = self.raw_ptr.take() {
             unsafe {
                 // take ownership and free when out of scope
                 Box::from_raw(raw);
@@ -576,3 +681,169 @@ impl<'a> Drop for DdsListener {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        DdsParticipant, DdsPublisher, DdsQos, DdsReader, DdsSubscriber, DdsTopic, DdsWriter,
+        SampleBuffer, TopicType,
+    };
+    use cdds_derive::Topic;
+    use serde_derive::{Deserialize, Serialize};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[derive(Serialize, Deserialize, Topic, Debug, PartialEq, Default)]
+    struct Alarm {
+        #[topic_key]
+        id: u32,
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let payload = panic::catch_unwind(|| panic!("static message")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "static message");
+        let payload = panic::catch_unwind(|| panic!("formatted {}", 1)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "formatted 1");
+        let payload = panic::catch_unwind(|| panic::panic_any(1)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "unknown payload");
+    }
+
+    #[test]
+    fn test_panicking_callback() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Alarm::create_topic(&participant, Some("panicking_callback"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+
+        let calls = Arc::new(AtomicU32::new(0));
+        let counted = calls.clone();
+        let listener = DdsListener::new()
+            .on_data_available(move |_| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                panic!("callback failed");
+            })
+            .hook();
+        let reader = DdsReader::create(&subscriber, topic, None, Some(listener.clone())).unwrap();
+        assert_eq!(listener.take_panic(), None);
+
+        for id in 0..2 {
+            writer.write(Arc::new(Alarm { id })).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(100));
+
+        // the listener keeps being called after a panic
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            listener.take_panic().as_deref(),
+            Some("panic in the on_data_available callback: callback failed")
+        );
+        assert_eq!(listener.take_panic(), None);
+        let mut samples = Alarm::create_sample_buffer(4);
+        assert_eq!(reader.take_now(&mut samples).unwrap().valid, 2);
+    }
+
+    #[test]
+    fn test_stateful_callback() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Alarm::create_topic(&participant, Some("stateful_callback"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+
+        // the callback keeps its own count, no atomic needed
+        let (report, counts) = std::sync::mpsc::channel();
+        let mut count = 0;
+        let listener = DdsListener::new()
+            .on_data_available(move |_| {
+                count += 1;
+                report.send(count).unwrap();
+            })
+            .hook();
+        let _reader = DdsReader::create(&subscriber, topic, None, Some(listener)).unwrap();
+
+        for id in 0..3 {
+            writer.write(Arc::new(Alarm { id })).unwrap();
+        }
+        let timeout = Duration::from_secs(1);
+        let counts: Vec<_> = (0..3)
+            .map(|_| counts.recv_timeout(timeout).unwrap())
+            .collect();
+        assert_eq!(counts, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_typed_status_callback() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Alarm::create_topic(&participant, Some("typed_status_callback"), None, None).unwrap();
+        let mut best_effort = DdsQos::create().unwrap();
+        best_effort.set_reliability(
+            dds_reliability_kind::DDS_RELIABILITY_BEST_EFFORT,
+            Duration::from_millis(100),
+        );
+        let mut reliable = DdsQos::create().unwrap();
+        reliable.set_reliability(
+            dds_reliability_kind::DDS_RELIABILITY_RELIABLE,
+            Duration::from_millis(100),
+        );
+
+        let last = Arc::new(Mutex::new(None));
+        let recorded = last.clone();
+        let listener = DdsListener::new()
+            .on_requested_incompatible_qos(move |_, status| {
+                *recorded.lock().unwrap() = Some(status);
+            })
+            .hook();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let _reader =
+            DdsReader::create(&subscriber, topic.clone(), Some(reliable), Some(listener)).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let _writer = DdsWriter::create(&publisher, topic, Some(best_effort), None).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+
+        // a best effort writer cannot serve a reliable reader
+        let status: RequestedIncompatibleQosStatus = last.lock().unwrap().unwrap();
+        assert_eq!(status.total_count, 1);
+        assert_eq!(status.last_policy_id, QosPolicyId::Reliability);
+    }
+
+    #[test]
+    fn test_typed_matched_callbacks() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Alarm::create_topic(&participant, Some("typed_matched_callbacks"), None, None).unwrap();
+
+        let (report, matched) = std::sync::mpsc::channel();
+        let publication = report.clone();
+        let writer_listener = DdsListener::new()
+            .on_publication_matched(move |_, status| {
+                publication.send(("writer", status.current_count)).unwrap();
+            })
+            .hook();
+        let reader_listener = DdsListener::new()
+            .on_subscription_matched(move |_, status| {
+                report.send(("reader", status.current_count)).unwrap();
+            })
+            .hook();
+
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let _writer =
+            DdsWriter::create(&publisher, topic.clone(), None, Some(writer_listener)).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let _reader = DdsReader::create(&subscriber, topic, None, Some(reader_listener)).unwrap();
+
+        let timeout = Duration::from_secs(1);
+        let mut events = vec![
+            matched.recv_timeout(timeout).unwrap(),
+            matched.recv_timeout(timeout).unwrap(),
+        ];
+        events.sort_unstable();
+        assert_eq!(events, vec![("reader", 1), ("writer", 1)]);
+    }
+}
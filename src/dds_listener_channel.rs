@@ -0,0 +1,411 @@
+/*
+    Copyright 2021 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Listener events delivered through a bounded channel.
+//!
+//! The callbacks of the listener only queue the event, so the application
+//! handles it on a thread of its own, or in a task, without holding up the
+//! Cyclone thread that raised it.
+//!
+//! `std::sync::mpsc` cannot drop the oldest event of a full channel, so the
+//! channel is a queue of its own. The receiver can be used from threads with
+//! `recv` and from tasks as a `Stream`.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_core::Stream;
+
+use crate::dds_api::*;
+use crate::dds_listener::DdsListener;
+use crate::dds_status::*;
+use crate::wakers::WakerRegistry;
+use cyclonedds_sys::DdsEntity;
+
+/// An event of a listener created by `DdsListener::to_channel`, with the
+/// entity it happened on
+#[derive(Clone)]
+pub enum DdsEvent {
+    DataAvailable(DdsEntity),
+    DataOnReaders(DdsEntity),
+    SampleLost(DdsEntity, SampleLostStatus),
+    SampleRejected(DdsEntity, SampleRejectedStatus),
+    LivelinessChanged(DdsEntity, LivelinessChangedStatus),
+    RequestedDeadlineMissed(DdsEntity, RequestedDeadlineMissedStatus),
+    RequestedIncompatibleQos(DdsEntity, RequestedIncompatibleQosStatus),
+    SubscriptionMatched(DdsEntity, SubscriptionMatchedStatus),
+    LivelinessLost(DdsEntity, LivelinessLostStatus),
+    OfferedDeadlineMissed(DdsEntity, OfferedDeadlineMissedStatus),
+    OfferedIncompatibleQos(DdsEntity, OfferedIncompatibleQosStatus),
+    PublicationMatched(DdsEntity, PublicationMatchedStatus),
+    InconsistentTopic(DdsEntity, InconsistentTopicStatus),
+}
+
+impl DdsEvent {
+    /// The entity the event happened on
+    pub fn entity(&self) -> &DdsEntity {
+        match self {
+            DdsEvent::DataAvailable(entity)
+            | DdsEvent::DataOnReaders(entity)
+            | DdsEvent::SampleLost(entity, _)
+            | DdsEvent::SampleRejected(entity, _)
+            | DdsEvent::LivelinessChanged(entity, _)
+            | DdsEvent::RequestedDeadlineMissed(entity, _)
+            | DdsEvent::RequestedIncompatibleQos(entity, _)
+            | DdsEvent::SubscriptionMatched(entity, _)
+            | DdsEvent::LivelinessLost(entity, _)
+            | DdsEvent::OfferedDeadlineMissed(entity, _)
+            | DdsEvent::OfferedIncompatibleQos(entity, _)
+            | DdsEvent::PublicationMatched(entity, _)
+            | DdsEvent::InconsistentTopic(entity, _) => entity,
+        }
+    }
+}
+
+/// What the listener does with an event when the channel is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Drop the oldest queued event to make room
+    DropOldest,
+    /// Drop the new event
+    DropNewest,
+    /// Wait for the receiver to make room. This holds up the Cyclone thread
+    /// that raised the event, and with it the delivery of other events.
+    Block,
+}
+
+struct Queue {
+    events: VecDeque<DdsEvent>,
+    dropped: u64,
+    wakers: WakerRegistry,
+    // the listener callbacks are gone, no more events will come
+    senders_gone: bool,
+    receiver_gone: bool,
+}
+
+struct Channel {
+    queue: Mutex<Queue>,
+    // signalled when an event is queued or room is made
+    changed: Condvar,
+    capacity: usize,
+    overflow: Overflow,
+}
+
+impl Channel {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn send(&self, event: DdsEvent) {
+        let mut queue = self.lock();
+        if self.overflow == Overflow::Block {
+            while queue.events.len() >= self.capacity && !queue.receiver_gone {
+                queue = self
+                    .changed
+                    .wait(queue)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+        }
+        if queue.receiver_gone {
+            return;
+        }
+        if queue.events.len() >= self.capacity {
+            queue.dropped += 1;
+            match self.overflow {
+                Overflow::DropNewest => return,
+                _ => {
+                    queue.events.pop_front();
+                }
+            }
+        }
+        queue.events.push_back(event);
+        queue.wakers.wake_all();
+        self.changed.notify_all();
+    }
+
+    // take the next event and make room for a blocked sender
+    fn pop(&self, queue: &mut Queue) -> Option<DdsEvent> {
+        let event = queue.events.pop_front();
+        if event.is_some() {
+            self.changed.notify_all();
+        }
+        event
+    }
+}
+
+// The sending side, shared by the callbacks of the listener. The receiver is
+// told when the listener is dropped.
+struct Sender(Arc<Channel>);
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        let mut queue = self.0.lock();
+        queue.senders_gone = true;
+        queue.wakers.wake_all();
+        self.0.changed.notify_all();
+    }
+}
+
+/// The receiving end of a listener created by `DdsListener::to_channel`. The
+/// events can be received by a thread, or by a task through the `Stream`
+/// implementation. The channel is disconnected once the listener is dropped
+/// and the queued events are received.
+pub struct DdsEventReceiver {
+    channel: Arc<Channel>,
+}
+
+impl DdsEventReceiver {
+    /// Wait for the next event. Returns `None` once the channel is
+    /// disconnected.
+    pub fn recv(&self) -> Option<DdsEvent> {
+        let mut queue = self.channel.lock();
+        loop {
+            if let Some(event) = self.channel.pop(&mut queue) {
+                return Some(event);
+            } else if queue.senders_gone {
+                return None;
+            }
+            queue = self
+                .channel
+                .changed
+                .wait(queue)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Wait for the next event for at most `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<DdsEvent> {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.channel.lock();
+        loop {
+            if let Some(event) = self.channel.pop(&mut queue) {
+                return Some(event);
+            }
+            let now = Instant::now();
+            if queue.senders_gone || now >= deadline {
+                return None;
+            }
+            queue = self
+                .channel
+                .changed
+                .wait_timeout(queue, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// The next event, if one is queued
+    pub fn try_recv(&self) -> Option<DdsEvent> {
+        self.channel.pop(&mut self.channel.lock())
+    }
+
+    /// Number of events dropped because the channel was full
+    pub fn dropped(&self) -> u64 {
+        self.channel.lock().dropped
+    }
+}
+
+impl Drop for DdsEventReceiver {
+    fn drop(&mut self) {
+        // release a blocked sender, later events are discarded
+        self.channel.lock().receiver_gone = true;
+        self.channel.changed.notify_all();
+    }
+}
+
+// The receiver is never pinned structurally
+impl Unpin for DdsEventReceiver {}
+
+impl Stream for DdsEventReceiver {
+    type Item = DdsEvent;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut queue = self.channel.lock();
+        if let Some(event) = self.channel.pop(&mut queue) {
+            Poll::Ready(Some(event))
+        } else if queue.senders_gone {
+            Poll::Ready(None)
+        } else {
+            queue.wakers.register(ctx.waker());
+            Poll::Pending
+        }
+    }
+}
+
+// add callbacks sending the enabled status events to the channel
+macro_rules! send_statuses {
+    ($listener:expr, $enabled:expr, $sender:expr, $($event:ident => $variant:ident($status:ty)),+) => {{
+        let mut listener = $listener;
+        $(
+            if $enabled.is_set(<$status as Status>::ID) {
+                let sender = $sender.clone();
+                listener = listener.$event(move |entity, status| {
+                    sender.0.send(DdsEvent::$variant(entity, status))
+                });
+            }
+        )+
+        listener
+    }};
+}
+
+impl DdsListener {
+    /// Send the events enabled in `enabled` to a channel holding up to
+    /// `capacity` events. Returns the hooked listener and the receiving end.
+    /// The other callbacks of the listener are kept.
+    pub fn to_channel(
+        self,
+        enabled: DdsStatus,
+        capacity: usize,
+        overflow: Overflow,
+    ) -> (DdsListener, DdsEventReceiver) {
+        let channel = Arc::new(Channel {
+            queue: Mutex::new(Queue {
+                events: VecDeque::with_capacity(capacity),
+                dropped: 0,
+                wakers: WakerRegistry::new(),
+                senders_gone: false,
+                receiver_gone: false,
+            }),
+            changed: Condvar::new(),
+            capacity: capacity.max(1),
+            overflow,
+        });
+        let sender = Arc::new(Sender(channel.clone()));
+
+        let mut listener = self;
+        if enabled.is_set(DDS_DATA_AVAILABLE_STATUS_ID) {
+            let sender = sender.clone();
+            listener = listener
+                .on_data_available(move |reader| sender.0.send(DdsEvent::DataAvailable(reader)));
+        }
+        if enabled.is_set(DDS_DATA_ON_READERS_STATUS_ID) {
+            let sender = sender.clone();
+            listener = listener.on_data_on_readers(move |subscriber| {
+                sender.0.send(DdsEvent::DataOnReaders(subscriber))
+            });
+        }
+        let listener = send_statuses!(
+            listener,
+            enabled,
+            sender,
+            on_sample_lost => SampleLost(SampleLostStatus),
+            on_sample_rejected => SampleRejected(SampleRejectedStatus),
+            on_liveliness_changed => LivelinessChanged(LivelinessChangedStatus),
+            on_requested_deadline_missed => RequestedDeadlineMissed(RequestedDeadlineMissedStatus),
+            on_requested_incompatible_qos => RequestedIncompatibleQos(RequestedIncompatibleQosStatus),
+            on_subscription_matched => SubscriptionMatched(SubscriptionMatchedStatus),
+            on_liveliness_lost => LivelinessLost(LivelinessLostStatus),
+            on_offered_deadline_missed => OfferedDeadlineMissed(OfferedDeadlineMissedStatus),
+            on_offered_incompatible_qos => OfferedIncompatibleQos(OfferedIncompatibleQosStatus),
+            on_publication_matched => PublicationMatched(PublicationMatchedStatus),
+            on_inconsistent_topic => InconsistentTopic(InconsistentTopicStatus)
+        );
+
+        (listener.hook(), DdsEventReceiver { channel })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        DDSError, DdsParticipant, DdsPublisher, DdsQos, DdsReader, DdsSubscriber, DdsTopic,
+        DdsWriter, Entity, SampleBuffer, TopicType,
+    };
+    use cdds_derive::Topic;
+    use futures::StreamExt;
+    use serde_derive::{Deserialize, Serialize};
+    use std::sync::Arc;
+    use tokio::runtime::Runtime;
+
+    #[derive(Serialize, Deserialize, Topic, Debug, PartialEq, Default)]
+    struct Alarm {
+        #[topic_key]
+        zone: u32,
+        level: u32,
+    }
+
+    #[test]
+    fn test_listener_channel() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Alarm::create_topic(&participant, Some("listener_channel"), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let enabled = DdsStatus::default()
+            .set(DDS_DATA_AVAILABLE_STATUS_ID)
+            .set(DDS_SUBSCRIPTION_MATCHED_STATUS_ID);
+        let (listener, events) = DdsListener::new().to_channel(enabled, 8, Overflow::DropNewest);
+        let reader = DdsReader::create(&subscriber, topic.clone(), None, Some(listener)).unwrap();
+
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic, None, None).unwrap();
+        writer.write(Arc::new(Alarm { zone: 1, level: 3 })).unwrap();
+
+        let timeout = Duration::from_secs(1);
+        match events.recv_timeout(timeout) {
+            Some(DdsEvent::SubscriptionMatched(entity, status)) => {
+                assert!(entity == *reader.entity());
+                assert_eq!(status.current_count, 1);
+            }
+            _ => panic!("expected the subscription to match"),
+        }
+        let event = events.recv_timeout(timeout).unwrap();
+        assert!(matches!(event, DdsEvent::DataAvailable(_)));
+        assert!(event.entity() == reader.entity());
+        assert!(events.try_recv().is_none());
+        assert_eq!(events.dropped(), 0);
+    }
+
+    #[test]
+    fn test_listener_channel_overflow() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Alarm::create_topic(&participant, Some("listener_overflow"), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let enabled = DdsStatus::default().set(DDS_DATA_AVAILABLE_STATUS_ID);
+        let (listener, events) = DdsListener::new().to_channel(enabled, 1, Overflow::DropOldest);
+        let reader = DdsReader::create(&subscriber, topic.clone(), None, Some(listener)).unwrap();
+
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic, None, None).unwrap();
+        for level in 0..3 {
+            writer.write(Arc::new(Alarm { zone: 2, level })).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(100));
+
+        // only the last event is kept
+        assert_eq!(events.dropped(), 2);
+        let rt = Runtime::new().unwrap();
+        let mut events = rt.block_on(async move {
+            let mut events = events;
+            assert!(matches!(
+                events.next().await,
+                Some(DdsEvent::DataAvailable(_))
+            ));
+            events
+        });
+        assert!(events.try_recv().is_none());
+
+        // the stream ends when the listener is gone
+        drop(reader);
+        rt.block_on(async {
+            assert!(events.next().await.is_none());
+        });
+    }
+}
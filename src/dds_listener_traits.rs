@@ -0,0 +1,391 @@
+/*
+    Copyright 2021 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Listeners implemented as traits, as an alternative to the closures of
+//! `DdsListener`.
+//!
+//! A struct implements the events it cares about and keeps its state in
+//! `self`. The other events do nothing. The methods are called from Cyclone
+//! threads, one at a time, so they take `&mut self`. See the threading notes of
+//! `dds_listener`.
+//!
+//! Rust cannot tell which methods are implemented, so each implementation lists
+//! them in `STATUSES` and only these callbacks are installed. The status
+//! conditions of the entity do not trigger for the listed events, as for a
+//! `DdsListener` with the same callbacks set, while the other events still
+//! trigger them and reach the listeners of the parent entities.
+//!
+//! As the calls are serialized, a method must not cause another event of the
+//! same listener on its own thread. Writing a sample from `on_data_available`
+//! of a participant listener that also serves the local reader of the sample
+//! deadlocks.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use cyclonedds_sys::DdsEntity;
+
+use crate::dds_api::*;
+use crate::dds_listener::DdsListener;
+use crate::dds_status::*;
+
+/// Events of a reader. Read the samples in `on_data_available` with
+/// `DdsReader::<T>::readn_from_entity_now`.
+#[allow(unused_variables)]
+pub trait ReaderListener: Send + 'static {
+    /// The statuses whose methods are implemented, such as
+    /// `DdsStatus::new().set(DDS_DATA_AVAILABLE_STATUS_ID)`
+    const STATUSES: DdsStatus;
+
+    fn on_data_available(&mut self, reader: &DdsEntity) {}
+    fn on_sample_lost(&mut self, reader: &DdsEntity, status: SampleLostStatus) {}
+    fn on_sample_rejected(&mut self, reader: &DdsEntity, status: SampleRejectedStatus) {}
+    fn on_liveliness_changed(&mut self, reader: &DdsEntity, status: LivelinessChangedStatus) {}
+    fn on_requested_deadline_missed(
+        &mut self,
+        reader: &DdsEntity,
+        status: RequestedDeadlineMissedStatus,
+    ) {
+    }
+    fn on_requested_incompatible_qos(
+        &mut self,
+        reader: &DdsEntity,
+        status: RequestedIncompatibleQosStatus,
+    ) {
+    }
+    fn on_subscription_matched(&mut self, reader: &DdsEntity, status: SubscriptionMatchedStatus) {}
+}
+
+/// Events of a writer
+#[allow(unused_variables)]
+pub trait WriterListener: Send + 'static {
+    /// The statuses whose methods are implemented. See `ReaderListener::STATUSES`.
+    const STATUSES: DdsStatus;
+
+    fn on_liveliness_lost(&mut self, writer: &DdsEntity, status: LivelinessLostStatus) {}
+    fn on_offered_deadline_missed(
+        &mut self,
+        writer: &DdsEntity,
+        status: OfferedDeadlineMissedStatus,
+    ) {
+    }
+    fn on_offered_incompatible_qos(
+        &mut self,
+        writer: &DdsEntity,
+        status: OfferedIncompatibleQosStatus,
+    ) {
+    }
+    fn on_publication_matched(&mut self, writer: &DdsEntity, status: PublicationMatchedStatus) {}
+}
+
+/// Events of the entities of a participant that do not handle them in a
+/// listener of their own
+#[allow(unused_variables)]
+pub trait ParticipantListener: Send + 'static {
+    /// The statuses whose methods are implemented. See `ReaderListener::STATUSES`.
+    const STATUSES: DdsStatus;
+
+    fn on_inconsistent_topic(&mut self, topic: &DdsEntity, status: InconsistentTopicStatus) {}
+    fn on_data_on_readers(&mut self, subscriber: &DdsEntity) {}
+    fn on_data_available(&mut self, reader: &DdsEntity) {}
+    fn on_sample_lost(&mut self, reader: &DdsEntity, status: SampleLostStatus) {}
+    fn on_sample_rejected(&mut self, reader: &DdsEntity, status: SampleRejectedStatus) {}
+    fn on_liveliness_changed(&mut self, reader: &DdsEntity, status: LivelinessChangedStatus) {}
+    fn on_requested_deadline_missed(
+        &mut self,
+        reader: &DdsEntity,
+        status: RequestedDeadlineMissedStatus,
+    ) {
+    }
+    fn on_requested_incompatible_qos(
+        &mut self,
+        reader: &DdsEntity,
+        status: RequestedIncompatibleQosStatus,
+    ) {
+    }
+    fn on_subscription_matched(&mut self, reader: &DdsEntity, status: SubscriptionMatchedStatus) {}
+    fn on_liveliness_lost(&mut self, writer: &DdsEntity, status: LivelinessLostStatus) {}
+    fn on_offered_deadline_missed(
+        &mut self,
+        writer: &DdsEntity,
+        status: OfferedDeadlineMissedStatus,
+    ) {
+    }
+    fn on_offered_incompatible_qos(
+        &mut self,
+        writer: &DdsEntity,
+        status: OfferedIncompatibleQosStatus,
+    ) {
+    }
+    fn on_publication_matched(&mut self, writer: &DdsEntity, status: PublicationMatchedStatus) {}
+}
+
+// The listener implementation shared by the callbacks
+struct Shared<L>(Arc<Mutex<L>>);
+
+impl<L> Shared<L> {
+    fn new(listener: L) -> Self {
+        Self(Arc::new(Mutex::new(listener)))
+    }
+
+    // run a method of the listener. A panic in an earlier call does not
+    // disable the listener.
+    fn call<F: FnOnce(&mut L)>(&self, method: F) {
+        let mut listener = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        method(&mut listener)
+    }
+}
+
+impl<L> Clone for Shared<L> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+// add the callbacks of the statuses in the mask, forwarding the events to the
+// methods of the same name
+macro_rules! forward_statuses {
+    ($listener:expr, $shared:expr, $statuses:expr, $($event:ident: $id:ident),+) => {{
+        let mut listener = $listener;
+        $(
+            if $statuses.is_set($id) {
+                let shared = $shared.clone();
+                listener = listener.$event(move |entity, status| {
+                    shared.call(|l| l.$event(&entity, status))
+                });
+            }
+        )+
+        listener
+    }};
+}
+
+impl DdsListener {
+    /// Create a hooked listener calling the methods of a `ReaderListener`
+    pub fn from_reader_listener<L: ReaderListener>(listener: L) -> Self {
+        let shared = Shared::new(listener);
+        let mut listener = DdsListener::new();
+        if L::STATUSES.is_set(DDS_DATA_AVAILABLE_STATUS_ID) {
+            let available = shared.clone();
+            listener = listener
+                .on_data_available(move |reader| available.call(|l| l.on_data_available(&reader)));
+        }
+        forward_statuses!(
+            listener,
+            shared,
+            L::STATUSES,
+            on_sample_lost: DDS_SAMPLE_LOST_STATUS_ID,
+            on_sample_rejected: DDS_SAMPLE_REJECTED_STATUS_ID,
+            on_liveliness_changed: DDS_LIVELINESS_CHANGED_STATUS_ID,
+            on_requested_deadline_missed: DDS_REQUESTED_DEADLINE_MISSED_STATUS_ID,
+            on_requested_incompatible_qos: DDS_REQUESTED_INCOMPATIBLE_QOS_STATUS_ID,
+            on_subscription_matched: DDS_SUBSCRIPTION_MATCHED_STATUS_ID
+        )
+        .hook()
+    }
+
+    /// Create a hooked listener calling the methods of a `WriterListener`
+    pub fn from_writer_listener<L: WriterListener>(listener: L) -> Self {
+        let shared = Shared::new(listener);
+        forward_statuses!(
+            DdsListener::new(),
+            shared,
+            L::STATUSES,
+            on_liveliness_lost: DDS_LIVELINESS_LOST_STATUS_ID,
+            on_offered_deadline_missed: DDS_OFFERED_DEADLINE_MISSED_STATUS_ID,
+            on_offered_incompatible_qos: DDS_OFFERED_INCOMPATIBLE_QOS_STATUS_ID,
+            on_publication_matched: DDS_PUBLICATION_MATCHED_STATUS_ID
+        )
+        .hook()
+    }
+
+    /// Create a hooked listener calling the methods of a `ParticipantListener`
+    pub fn from_participant_listener<L: ParticipantListener>(listener: L) -> Self {
+        let shared = Shared::new(listener);
+        let mut listener = DdsListener::new();
+        if L::STATUSES.is_set(DDS_DATA_ON_READERS_STATUS_ID) {
+            let readers = shared.clone();
+            listener = listener.on_data_on_readers(move |subscriber| {
+                readers.call(|l| l.on_data_on_readers(&subscriber))
+            });
+        }
+        if L::STATUSES.is_set(DDS_DATA_AVAILABLE_STATUS_ID) {
+            let available = shared.clone();
+            listener = listener
+                .on_data_available(move |reader| available.call(|l| l.on_data_available(&reader)));
+        }
+        forward_statuses!(
+            listener,
+            shared,
+            L::STATUSES,
+            on_inconsistent_topic: DDS_INCONSISTENT_TOPIC_STATUS_ID,
+            on_sample_lost: DDS_SAMPLE_LOST_STATUS_ID,
+            on_sample_rejected: DDS_SAMPLE_REJECTED_STATUS_ID,
+            on_liveliness_changed: DDS_LIVELINESS_CHANGED_STATUS_ID,
+            on_requested_deadline_missed: DDS_REQUESTED_DEADLINE_MISSED_STATUS_ID,
+            on_requested_incompatible_qos: DDS_REQUESTED_INCOMPATIBLE_QOS_STATUS_ID,
+            on_subscription_matched: DDS_SUBSCRIPTION_MATCHED_STATUS_ID,
+            on_liveliness_lost: DDS_LIVELINESS_LOST_STATUS_ID,
+            on_offered_deadline_missed: DDS_OFFERED_DEADLINE_MISSED_STATUS_ID,
+            on_offered_incompatible_qos: DDS_OFFERED_INCOMPATIBLE_QOS_STATUS_ID,
+            on_publication_matched: DDS_PUBLICATION_MATCHED_STATUS_ID
+        )
+        .hook()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        DDSError, DdsParticipant, DdsPublisher, DdsQos, DdsReader, DdsSubscriber, DdsTopic,
+        DdsWriter, Entity, ParticipantBuilder, ReaderBuilder, SampleBuffer, TopicType,
+        WriterBuilder,
+    };
+    use cdds_derive::Topic;
+    use serde_derive::{Deserialize, Serialize};
+    use std::sync::mpsc::{self, Sender};
+    use std::time::Duration;
+
+    #[derive(Serialize, Deserialize, Topic, Debug, PartialEq, Default)]
+    struct Reading {
+        #[topic_key]
+        sensor: u32,
+        value: u32,
+    }
+
+    // sums up the values it receives
+    struct Totalizer {
+        buffer: SampleBuffer<Reading>,
+        total: u32,
+        matched: bool,
+        report: Sender<(bool, u32)>,
+    }
+
+    impl ReaderListener for Totalizer {
+        const STATUSES: DdsStatus = DdsStatus::new()
+            .set(DDS_DATA_AVAILABLE_STATUS_ID)
+            .set(DDS_SUBSCRIPTION_MATCHED_STATUS_ID);
+
+        fn on_data_available(&mut self, reader: &DdsEntity) {
+            DdsReader::<Reading>::readn_from_entity_now(reader, &mut self.buffer, true).unwrap();
+            self.total += self.buffer.iter().map(|r| r.value).sum::<u32>();
+            self.report.send((self.matched, self.total)).unwrap();
+        }
+
+        fn on_subscription_matched(&mut self, _: &DdsEntity, status: SubscriptionMatchedStatus) {
+            self.matched = status.current_count > 0;
+        }
+    }
+
+    #[test]
+    fn test_reader_listener_impl() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Reading::create_topic(&participant, Some("reader_listener_impl"), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let (report, totals) = mpsc::channel();
+        let _reader = ReaderBuilder::new()
+            .with_listener_impl(Totalizer {
+                buffer: Reading::create_sample_buffer(4),
+                total: 0,
+                matched: false,
+                report,
+            })
+            .create(&subscriber, topic.clone())
+            .unwrap();
+
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic, None, None).unwrap();
+        for (sensor, value) in [(1, 10), (2, 20)] {
+            writer
+                .write(std::sync::Arc::new(Reading { sensor, value }))
+                .unwrap();
+        }
+
+        let timeout = Duration::from_secs(1);
+        assert_eq!(totals.recv_timeout(timeout).unwrap(), (true, 10));
+        assert_eq!(totals.recv_timeout(timeout).unwrap(), (true, 30));
+    }
+
+    // reports the number of matched readers
+    struct MatchReporter(Sender<u32>);
+
+    impl WriterListener for MatchReporter {
+        const STATUSES: DdsStatus = DdsStatus::new().set(DDS_PUBLICATION_MATCHED_STATUS_ID);
+
+        fn on_publication_matched(&mut self, _: &DdsEntity, status: PublicationMatchedStatus) {
+            self.0.send(status.current_count).unwrap();
+        }
+    }
+
+    impl ParticipantListener for MatchReporter {
+        const STATUSES: DdsStatus = DdsStatus::new().set(DDS_PUBLICATION_MATCHED_STATUS_ID);
+
+        fn on_publication_matched(&mut self, _: &DdsEntity, status: PublicationMatchedStatus) {
+            self.0.send(status.current_count).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_writer_listener_impl() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Reading::create_topic(&participant, Some("writer_listener_impl"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let (report, matched) = mpsc::channel();
+        let _writer = WriterBuilder::new()
+            .with_listener_impl(MatchReporter(report))
+            .create(&publisher, topic.clone())
+            .unwrap();
+
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = DdsReader::create(&subscriber, topic, None, None).unwrap();
+        let timeout = Duration::from_secs(1);
+        assert_eq!(matched.recv_timeout(timeout).unwrap(), 1);
+        drop(reader);
+        assert_eq!(matched.recv_timeout(timeout).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_participant_listener_impl() {
+        let (report, matched) = mpsc::channel();
+        let participant = ParticipantBuilder::new()
+            .with_listener_impl(MatchReporter(report))
+            .create()
+            .unwrap();
+        let topic =
+            Reading::create_topic(&participant, Some("participant_listener_impl"), None, None)
+                .unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = DdsReader::create(&subscriber, topic.clone(), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic, None, None).unwrap();
+
+        // the writer has no listener of its own
+        let timeout = Duration::from_secs(1);
+        assert_eq!(matched.recv_timeout(timeout).unwrap(), 1);
+
+        // the data available callback is not installed, so the status is still
+        // set on the reader
+        writer
+            .write(std::sync::Arc::new(Reading {
+                sensor: 1,
+                value: 1,
+            }))
+            .unwrap();
+        let changes = dds_get_status_changes(reader.entity()).unwrap();
+        assert!(changes.is_set(DDS_DATA_AVAILABLE_STATUS_ID));
+    }
+}
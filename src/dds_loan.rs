@@ -0,0 +1,182 @@
+/*
+    Copyright 2021 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Zero-copy reads of fixed size types.
+//!
+//! The samples are read into a buffer loaned from the reader. Each sample
+//! references the received data, which is the iceoryx chunk itself when shared
+//! memory is in use. The loan is returned when the `LoanedSamples` are dropped.
+
+use std::ffi::c_void;
+
+use cyclonedds_sys::*;
+
+use crate::dds_reader::DdsReader;
+use crate::serdes::{Sample, SampleInfo, TopicFixedSize};
+use crate::Entity;
+
+/// Samples read or taken on loan from a reader. Created by
+/// `DdsReader::read_loan` and `DdsReader::take_loan`.
+pub struct LoanedSamples<'a, T: Sized + TopicFixedSize> {
+    reader: &'a DdsReader<T>,
+    // Cyclone fills in a pointer to each sample of the loan
+    buf: Vec<*mut c_void>,
+    info: Vec<SampleInfo>,
+    count: usize,
+}
+
+impl<'a, T> LoanedSamples<'a, T>
+where
+    T: Sized + TopicFixedSize,
+{
+    pub(crate) fn new(reader: &'a DdsReader<T>, max: usize, take: bool) -> Result<Self, DDSError> {
+        let max = max.max(1);
+        let mut loaned = Self {
+            reader,
+            buf: vec![std::ptr::null_mut(); reader.loan_buffer_len(max)],
+            info: vec![SampleInfo::default(); max],
+            count: 0,
+        };
+        // a null first entry asks Cyclone for a loan
+        let ret = unsafe {
+            let entity = reader.entity().entity();
+            let buf = loaned.buf.as_mut_ptr();
+            let info = loaned.info.as_mut_ptr() as *mut dds_sample_info;
+            if take {
+                dds_take(entity, buf, info, max as size_t, max as u32)
+            } else {
+                dds_read(entity, buf, info, max as size_t, max as u32)
+            }
+        };
+        if ret >= 0 {
+            loaned.count = ret as usize;
+            Ok(loaned)
+        } else {
+            Err(DDSError::from(ret))
+        }
+    }
+
+    /// Number of samples, valid or not
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// True if no samples were available
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The data of a sample. Invalid samples have no data.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.count {
+            let sample = unsafe { &*(self.buf[index] as *const Sample<T>) };
+            sample.try_deref()
+        } else {
+            None
+        }
+    }
+
+    /// The sample info of a sample
+    pub fn info(&self, index: usize) -> &SampleInfo {
+        &self.info[..self.count][index]
+    }
+
+    /// Iterate over the samples with data
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.count).filter_map(move |i| self.get(i))
+    }
+
+    /// Iterate over all samples with their sample info
+    pub fn iter_with_info(&self) -> impl Iterator<Item = (Option<&T>, &SampleInfo)> {
+        (0..self.count).map(move |i| (self.get(i), &self.info[i]))
+    }
+}
+
+impl<'a, T> Drop for LoanedSamples<'a, T>
+where
+    T: Sized + TopicFixedSize,
+{
+    fn drop(&mut self) {
+        // Cyclone does not hand out a loan when nothing was read
+        if self.buf[0].is_null() {
+            return;
+        }
+        unsafe {
+            // release the received data now, the loan may be kept by the reader
+            for p in &self.buf[..self.count] {
+                (*(*p as *mut Sample<T>)).clear();
+            }
+            dds_return_loan(
+                self.reader.entity().entity(),
+                self.buf.as_mut_ptr(),
+                self.count as i32,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        DDSError, DdsListener, DdsParticipant, DdsPublisher, DdsQos, DdsReader, DdsSubscriber,
+        DdsTopic, DdsWriter, SampleBuffer, TopicFixedSize, TopicType,
+    };
+    use cdds_derive::TopicFixedSize;
+    use serde_derive::{Deserialize, Serialize};
+    use std::sync::Arc;
+
+    #[derive(Serialize, Deserialize, TopicFixedSize, Debug, Clone, PartialEq, Default)]
+    struct Reading {
+        #[topic_key]
+        sensor: u32,
+        value: [u16; 4],
+    }
+
+    #[test]
+    fn test_loaned_read() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic = Reading::create_topic(&participant, Some("loaned_read"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = DdsReader::create(&subscriber, topic, None, None).unwrap();
+
+        assert!(reader.take_loan(4).unwrap().is_empty());
+
+        for sensor in 0..2 {
+            let value = [sensor as u16; 4];
+            writer.write(Arc::new(Reading { sensor, value })).unwrap();
+        }
+
+        {
+            let loaned = reader.read_loan(4).unwrap();
+            assert_eq!(loaned.len(), 2);
+            let mut sensors: Vec<_> = loaned.iter().map(|r| r.sensor).collect();
+            sensors.sort_unstable();
+            assert_eq!(sensors, vec![0, 1]);
+        }
+
+        // the loan can be taken again once returned
+        let loaned = reader.take_loan(4).unwrap();
+        for (reading, info) in loaned.iter_with_info() {
+            let reading = reading.unwrap();
+            assert!(info.valid_data());
+            assert_eq!(reading.value, [reading.sensor as u16; 4]);
+        }
+        drop(loaned);
+        assert!(reader.read_loan(4).unwrap().is_empty());
+    }
+}
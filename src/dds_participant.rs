@@ -14,6 +14,7 @@
     limitations under the License.
 */
 
+use crate::dds_listener_traits::ParticipantListener;
 use crate::{dds_listener::DdsListener, dds_qos::DdsQos, DdsReadable, DdsWritable, Entity};
 pub use cyclonedds_sys::{DDSError, DdsDomainId, DdsEntity};
 use std::convert::From;
@@ -66,6 +67,12 @@ impl ParticipantBuilder {
         self
     }
 
+    /// Create a participant with a listener implementing `ParticipantListener`
+    pub fn with_listener_impl<L: ParticipantListener>(mut self, listener: L) -> Self {
+        self.maybe_listener = Some(DdsListener::from_participant_listener(listener));
+        self
+    }
+
     pub fn create(self) -> Result<DdsParticipant, DDSError> {
         DdsParticipant::create(self.maybe_domain, self.maybe_qos, self.maybe_listener)
     }
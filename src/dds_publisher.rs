@@ -14,6 +14,7 @@
     limitations under the License.
 */
 
+use crate::dds_api::CoherentSet;
 use crate::{DdsListener, DdsParticipant, DdsQos, DdsWritable};
 pub use cyclonedds_sys::{DDSError, DdsDomainId, DdsEntity};
 use std::convert::From;
@@ -70,6 +71,16 @@ impl<'a> DdsPublisher {
             }
         }
     }
+
+    /// Begin a coherent set of changes. Samples written by the writers of
+    /// this publisher are delivered together once the returned guard is
+    /// committed or dropped. The presentation Qos of the publisher and of the
+    /// subscribers must enable `coherent_access`; readers then receive the
+    /// complete set or nothing. Cyclone versions before 0.9 do not implement
+    /// coherent sets and return `DDSError::Unsupported`.
+    pub fn begin_coherent(&self) -> Result<CoherentSet<'_>, DDSError> {
+        CoherentSet::begin(&self.0)
+    }
 }
 
 impl<'a> DdsWritable for DdsPublisher {
@@ -77,3 +88,107 @@ impl<'a> DdsWritable for DdsPublisher {
         &self.0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dds_qos::dds_presentation_access_scope_kind;
+    use crate::{DdsReader, DdsSubscriber, DdsTopic, DdsWriter, SampleBuffer, TopicType};
+    use cdds_derive::Topic;
+    use serde_derive::{Deserialize, Serialize};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+
+    #[derive(Serialize, Deserialize, Topic, Debug, PartialEq, Default)]
+    struct Waypoint {
+        #[topic_key]
+        index: u32,
+        x: f64,
+        y: f64,
+    }
+
+    fn coherent_qos() -> DdsQos {
+        let mut qos = DdsQos::create().unwrap();
+        qos.set_presentation(
+            dds_presentation_access_scope_kind::DDS_PRESENTATION_TOPIC,
+            true,
+            false,
+        );
+        qos
+    }
+
+    // How long to wait for samples that should not arrive
+    const NOT_DELIVERED_WAIT: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn test_coherent_set() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic = Waypoint::create_topic(&participant, None, None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, Some(coherent_qos()), None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, Some(coherent_qos()), None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let reader = DdsReader::create(&subscriber, topic, None, None).unwrap();
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(writer.wait_for_readers(1, Duration::from_secs(5)))
+            .unwrap();
+
+        let mut samples = Waypoint::create_sample_buffer(4);
+        let mut write_waypoints = || {
+            for index in 0..2 {
+                writer
+                    .write(Arc::new(Waypoint {
+                        index,
+                        ..Default::default()
+                    }))
+                    .unwrap();
+            }
+        };
+        match publisher.begin_coherent() {
+            Ok(set) => {
+                write_waypoints();
+                // nothing is delivered until the set is complete
+                assert!(!reader.wait_for_unread(&participant, NOT_DELIVERED_WAIT));
+                assert!(reader.take_now(&mut samples).unwrap().is_empty());
+
+                set.commit().unwrap();
+            }
+            // Cyclone before 0.9 has no coherent sets, the samples are
+            // delivered as they are written
+            Err(e) => {
+                assert_eq!(e, DDSError::Unsupported);
+                write_waypoints();
+            }
+        }
+        assert!(reader.wait_for_unread(&participant, Duration::from_secs(1)));
+        assert_eq!(reader.take_now(&mut samples).unwrap().valid, 2);
+    }
+
+    #[test]
+    fn test_write_flush() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic = Waypoint::create_topic(&participant, Some("write_flush"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let reader = DdsReader::create(&subscriber, topic, None, None).unwrap();
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(writer.wait_for_readers(1, Duration::from_secs(5)))
+            .unwrap();
+
+        for index in 0..3 {
+            writer
+                .write(Arc::new(Waypoint {
+                    index,
+                    ..Default::default()
+                }))
+                .unwrap();
+        }
+        writer.write_flush();
+        assert!(reader.wait_for_unread(&participant, Duration::from_secs(1)));
+        let mut samples = Waypoint::create_sample_buffer(4);
+        assert_eq!(reader.take_now(&mut samples).unwrap().valid, 3);
+    }
+}
@@ -44,6 +44,11 @@ impl DdsQos {
         }
     }
 
+    /// Take ownership of a Qos allocated by Cyclone
+    pub(crate) unsafe fn from_raw(qos: *mut dds_qos_t) -> Self {
+        DdsQos(qos)
+    }
+
     pub fn merge(&mut self, src: &Self) {
         unsafe {
             dds_merge_qos(self.0, src.0);
@@ -222,7 +227,37 @@ impl DdsQos {
         unsafe { dds_qset_partition1(self.0, name.as_ptr()) }
         self
     }
-    //TODO:  Not implementing any getters for now
+
+    /// The partitions of the Qos. Empty if the partition policy is not set.
+    pub fn partitions(&self) -> Vec<String> {
+        let mut n = 0u32;
+        let mut ps: *mut *mut std::os::raw::c_char = std::ptr::null_mut();
+        unsafe {
+            if !dds_qget_partition(self.0, &mut n, &mut ps) || ps.is_null() {
+                return Vec::new();
+            }
+            let partitions = (0..n as usize)
+                .map(|i| {
+                    let p = *ps.add(i);
+                    let name = std::ffi::CStr::from_ptr(p).to_string_lossy().into_owned();
+                    dds_free(p as *mut std::ffi::c_void);
+                    name
+                })
+                .collect();
+            dds_free(ps as *mut std::ffi::c_void);
+            partitions
+        }
+    }
+
+    /// The ownership strength of the Qos, if set
+    pub fn ownership_strength(&self) -> Option<i32> {
+        let mut value = 0;
+        if unsafe { dds_qget_ownership_strength(self.0, &mut value) } {
+            Some(value)
+        } else {
+            None
+        }
+    }
 }
 
 impl Default for DdsQos {
@@ -0,0 +1,230 @@
+/*
+    Copyright 2021 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Query conditions that filter the samples of a reader with a Rust predicate.
+//!
+//! Cyclone calls the filter of a query condition with a sample allocated by the
+//! sertype, but without any user data. Each condition is given one of a fixed
+//! number of filter functions, which finds the predicate of the condition in a
+//! table. The number of filter functions, `MAX_QUERY_CONDITIONS`, limits the
+//! number of query conditions that can exist at the same time in the process,
+//! whatever their type or participant.
+
+use std::ffi::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, RwLock};
+
+use cyclonedds_sys::*;
+
+use crate::dds_reader::{DdsReader, Inner, ReadResult};
+use crate::error::QueryConditionError;
+use crate::serdes::{Sample, SampleBuffer, TopicType};
+use crate::Entity;
+
+/// Predicate over a sample allocated by the sertype
+type Predicate = Arc<dyn Fn(*const c_void) -> bool + Send + Sync>;
+
+/// The predicates of the query conditions, indexed by filter slot
+static PREDICATES: RwLock<Vec<Option<Predicate>>> = RwLock::new(Vec::new());
+
+type FilterFn = unsafe extern "C" fn(*const c_void) -> bool;
+
+unsafe extern "C" fn call_predicate<const SLOT: usize>(sample: *const c_void) -> bool {
+    // release the table before calling into the predicate
+    let predicate = match PREDICATES.read() {
+        Ok(predicates) => predicates.get(SLOT).cloned().flatten(),
+        Err(_) => None,
+    };
+    match predicate {
+        // a panic must not unwind into Cyclone
+        Some(predicate) => {
+            panic::catch_unwind(AssertUnwindSafe(|| predicate(sample))).unwrap_or(false)
+        }
+        None => false,
+    }
+}
+
+macro_rules! filter_fns {
+    ($($slot:literal)*) => {
+        [$(call_predicate::<$slot> as FilterFn),*]
+    };
+}
+
+/// The maximum number of query conditions that can exist at the same time in
+/// the process
+pub const MAX_QUERY_CONDITIONS: usize = 64;
+
+/// One filter function per slot
+const FILTER_FNS: [FilterFn; MAX_QUERY_CONDITIONS] = filter_fns!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+    32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63
+);
+
+// store the predicate in a free slot
+fn register(predicate: Predicate) -> Result<usize, QueryConditionError> {
+    let mut predicates = PREDICATES.write().unwrap();
+    if let Some(slot) = predicates.iter().position(Option::is_none) {
+        predicates[slot] = Some(predicate);
+        Ok(slot)
+    } else if predicates.len() < FILTER_FNS.len() {
+        predicates.push(Some(predicate));
+        Ok(predicates.len() - 1)
+    } else {
+        Err(QueryConditionError::TooManyConditions(MAX_QUERY_CONDITIONS))
+    }
+}
+
+fn unregister(slot: usize) {
+    PREDICATES.write().unwrap()[slot] = None;
+}
+
+/// A condition on a reader that triggers for the samples matching both a state
+/// mask and a predicate. It can be attached to a `DdsWaitset`, and the matching
+/// samples can be read or taken through it. The condition keeps the reader
+/// alive and is deleted on drop.
+pub struct DdsQueryCondition<T: Sized + TopicType> {
+    entity: DdsEntity,
+    _reader: Arc<Inner<T>>,
+    slot: usize,
+}
+
+impl<T> DdsQueryCondition<T>
+where
+    T: Sized + TopicType,
+{
+    pub(crate) fn create<F>(
+        reader: &DdsReader<T>,
+        mask: StateMask,
+        predicate: F,
+    ) -> Result<Self, QueryConditionError>
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        let slot = register(Arc::new(move |sample: *const c_void| {
+            // the sample was allocated by the sertype of T
+            let sample = unsafe { &*(sample as *const Sample<T>) };
+            sample.try_deref().is_some_and(&predicate)
+        }))?;
+
+        unsafe {
+            let condition =
+                dds_create_querycondition(reader.entity().entity(), *mask, Some(FILTER_FNS[slot]));
+            if condition > 0 {
+                Ok(Self {
+                    entity: DdsEntity::new(condition),
+                    _reader: reader.shared(),
+                    slot,
+                })
+            } else {
+                unregister(slot);
+                Err(DDSError::from(condition).into())
+            }
+        }
+    }
+
+    /// read the samples matching the condition synchronously
+    pub fn read_now(&self, buf: &mut SampleBuffer<T>) -> Result<ReadResult, DDSError> {
+        DdsReader::<T>::readn_from_entity_now(&self.entity, buf, false)
+    }
+
+    /// take the samples matching the condition synchronously
+    pub fn take_now(&self, buf: &mut SampleBuffer<T>) -> Result<ReadResult, DDSError> {
+        DdsReader::<T>::readn_from_entity_now(&self.entity, buf, true)
+    }
+}
+
+impl<T> Entity for DdsQueryCondition<T>
+where
+    T: Sized + TopicType,
+{
+    fn entity(&self) -> &DdsEntity {
+        &self.entity
+    }
+}
+
+impl<T> Drop for DdsQueryCondition<T>
+where
+    T: Sized + TopicType,
+{
+    fn drop(&mut self) {
+        // the filter is no longer called once the condition is deleted
+        unsafe { dds_delete(self.entity.entity()) };
+        unregister(self.slot);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        DdsListener, DdsParticipant, DdsPublisher, DdsQos, DdsSubscriber, DdsTopic, DdsWaitset,
+        DdsWriter,
+    };
+    use cdds_derive::Topic;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Topic, Debug, Clone, PartialEq, Default)]
+    struct Vehicle {
+        #[topic_key]
+        id: u32,
+        speed: u32,
+    }
+
+    #[test]
+    fn test_query_condition() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Vehicle::create_topic(&participant, Some("query_condition"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let mut qos = DdsQos::create().unwrap();
+        qos.set_history(dds_history_kind::DDS_HISTORY_KEEP_LAST, 10);
+        let reader = DdsReader::create(&subscriber, topic, Some(qos), None).unwrap();
+
+        let limit = 50;
+        let speeding = reader
+            .create_querycondition(StateMask::from(State::DdsAnyState), move |v: &Vehicle| {
+                v.speed > limit
+            })
+            .unwrap();
+        let mut waitset = DdsWaitset::<()>::create(&participant).unwrap();
+        waitset.attach(&speeding, &()).unwrap();
+
+        writer
+            .write(Arc::new(Vehicle { id: 1, speed: 30 }))
+            .unwrap();
+        let mut triggered = Vec::with_capacity(1);
+        assert!(waitset.wait(&mut triggered, 0).unwrap().is_empty());
+
+        writer
+            .write(Arc::new(Vehicle { id: 2, speed: 80 }))
+            .unwrap();
+        let mut triggered = Vec::with_capacity(1);
+        assert_eq!(
+            waitset.wait(&mut triggered, 1_000_000_000).unwrap().len(),
+            1
+        );
+
+        let mut samples = Vehicle::create_sample_buffer(4);
+        assert_eq!(speeding.take_now(&mut samples).unwrap().valid, 1);
+        assert_eq!(samples.iter().next().unwrap().id, 2);
+
+        // the slower vehicle is still in the reader
+        assert_eq!(reader.take_now(&mut samples).unwrap().valid, 1);
+        assert_eq!(samples.iter().next().unwrap().id, 1);
+    }
+}
@@ -15,23 +15,75 @@
 */
 
 use cyclonedds_sys::*;
-use std::convert::From;
+use std::convert::{From, TryFrom};
 use std::future::Future;
 use std::os::raw::c_void;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::task::{Context, Poll, Waker};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 //use std::convert::TryInto;
 
 pub use cyclonedds_sys::{DdsDomainId, DdsEntity};
 
 use std::marker::PhantomData;
 
-use crate::error::ReaderError;
-use crate::serdes::{SampleBuffer, TopicType};
+use crate::common::{BlockingFuture, Cancel};
+use crate::dds_api::{self, InstanceHandle, MatchedEndpoint, StateFilter};
+use crate::dds_instance_events::{InstanceEventStream, InstanceEvents};
+use crate::dds_listener_traits::ReaderListener;
+use crate::dds_loan::LoanedSamples;
+use crate::dds_querycondition::DdsQueryCondition;
+use crate::dds_status::{
+    LivelinessChangedStatus, ReaderStatus, RequestedDeadlineMissedStatus,
+    RequestedIncompatibleQosStatus, SampleLostStatus, SampleRejectedStatus,
+    SubscriptionMatchedStatus,
+};
+use crate::dds_status_events::{self, StatusEvents, StatusSlots, StatusStream};
+use crate::dds_stream::{SampleInfoStream, SampleStream, SharedSampleStream};
+use crate::error::{QueryConditionError, ReaderError};
+use crate::serdes::{SampleBuffer, SampleInfo, TopicFixedSize, TopicType};
+use crate::wakers::WakerRegistry;
 use crate::Sample;
 use crate::{dds_listener::DdsListener, dds_qos::DdsQos, dds_topic::DdsTopic, DdsReadable, Entity};
 
+/// The outcome of a read or take. Invalid samples carry no data, they notify
+/// a change in the state of an instance such as a dispose.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReadResult {
+    /// Number of samples with data
+    pub valid: usize,
+    /// Number of samples without data
+    pub invalid: usize,
+}
+
+impl ReadResult {
+    /// Total number of samples read
+    pub fn len(&self) -> usize {
+        self.valid + self.invalid
+    }
+
+    /// True if no samples were available
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // count the samples from the return code of a Cyclone read or take
+    fn from_return<T: TopicType>(buf: &SampleBuffer<T>, ret: i32) -> Result<Self, DDSError> {
+        if ret < 0 {
+            return Err(DDSError::from(ret));
+        }
+        let valid = (0..ret as usize)
+            .filter(|i| buf.is_valid_sample(*i))
+            .count();
+        Ok(Self {
+            valid,
+            invalid: ret as usize - valid,
+        })
+    }
+}
+
 /// Builder structure for reader
 pub struct ReaderBuilder<T: TopicType> {
     maybe_qos: Option<DdsQos>,
@@ -75,6 +127,13 @@ where
         self
     }
 
+    /// Create a reader with a listener implementing `ReaderListener`.
+    /// Like `with_listener`, this is ignored if an async reader is created.
+    pub fn with_listener_impl<L: ReaderListener>(mut self, listener: L) -> Self {
+        self.maybe_listener = Some(DdsListener::from_reader_listener(listener));
+        self
+    }
+
     pub fn create(
         self,
         entity: &dyn DdsReadable,
@@ -83,26 +142,26 @@ where
         if self.is_async {
             DdsReader::create_async(entity, topic, self.maybe_qos)
         } else {
-            DdsReader::create_sync_or_async(
-                entity,
-                topic,
-                self.maybe_qos,
-                self.maybe_listener,
-                ReaderType::Sync,
-            )
+            DdsReader::create(entity, topic, self.maybe_qos, self.maybe_listener)
         }
     }
 }
 
+/// Wakers of the tasks waiting for data on an async reader
+pub(crate) type ReaderWaker = Arc<Mutex<WakerRegistry>>;
+
 enum ReaderType {
-    Async(Arc<Mutex<(Option<Waker>, Result<(), crate::error::ReaderError>)>>),
+    Async(ReaderWaker),
     Sync,
 }
 
-struct Inner<T: Sized + TopicType> {
+pub(crate) struct Inner<T: Sized + TopicType> {
     entity: DdsEntity,
     _listener: Option<DdsListener>,
     reader_type: ReaderType,
+    status_events: StatusEvents,
+    // the largest number of samples loaned at once
+    loan_len: AtomicUsize,
     _phantom: PhantomData<T>,
     // The callback closures that can be attached to a reader
 }
@@ -115,13 +174,27 @@ impl<'a, T> DdsReader<T>
 where
     T: Sized + TopicType,
 {
+    /// Create a reader. The status changes of a reader created without a
+    /// listener can be streamed with `status_stream` and `status_changed`.
     pub fn create(
         entity: &dyn DdsReadable,
         topic: DdsTopic<T>,
         maybe_qos: Option<DdsQos>,
         maybe_listener: Option<DdsListener>,
     ) -> Result<Self, DDSError> {
-        Self::create_sync_or_async(entity, topic, maybe_qos, maybe_listener, ReaderType::Sync)
+        let events = if maybe_listener.is_some() {
+            StatusSlots::new_closed()
+        } else {
+            StatusSlots::new()
+        };
+        Self::create_sync_or_async(
+            entity,
+            topic,
+            maybe_qos,
+            maybe_listener,
+            ReaderType::Sync,
+            events,
+        )
     }
 
     fn create_sync_or_async(
@@ -130,6 +203,7 @@ where
         maybe_qos: Option<DdsQos>,
         maybe_listener: Option<DdsListener>,
         reader_type: ReaderType,
+        status_events: StatusEvents,
     ) -> Result<Self, DDSError> {
         unsafe {
             let w = dds_create_reader(
@@ -147,6 +221,8 @@ where
                         entity: DdsEntity::new(w),
                         _listener: maybe_listener,
                         reader_type,
+                        status_events,
+                        loan_len: AtomicUsize::new(0),
                         _phantom: PhantomData,
                     }),
                 })
@@ -157,112 +233,240 @@ where
     }
 
     /// Create an async reader. This constructor must be used if using any of the async functions.
+    /// The status changes of the reader can be streamed with `status_stream` and `status_changed`.
     pub fn create_async(
         entity: &dyn DdsReadable,
         topic: DdsTopic<T>,
         maybe_qos: Option<DdsQos>,
     ) -> Result<Self, DDSError> {
-        let waker = Arc::new(<Mutex<(
-            Option<Waker>,
-            Result<(), crate::error::ReaderError>,
-        )>>::new((None, Ok(()))));
-        let waker_cb = waker.clone();
-        let requested_deadline_waker = waker.clone();
-
-        let listener = DdsListener::new()
-            .on_data_available(move |_entity| {
-                //println!("Data available ");
-                let mut maybe_waker = waker_cb.lock().unwrap();
-                if let Some(waker) = maybe_waker.0.take() {
-                    waker.wake();
-                }
-            })
-            .on_requested_deadline_missed(move |entity, status| {
-                println!(
-                    "Deadline missed: Entity:{:?} Status:{:?}",
-                    unsafe { entity.entity() },
-                    status
-                );
-                let mut maybe_waker = requested_deadline_waker.lock().unwrap();
-                maybe_waker.1 = Err(ReaderError::RequestedDeadLineMissed);
-                if let Some(waker) = maybe_waker.0.take() {
-                    waker.wake();
-                }
-            })
-            .hook();
+        let waker: ReaderWaker = Arc::new(Mutex::new(WakerRegistry::new()));
+        let listener = data_available_listener(&waker).hook();
 
-        match Self::create_sync_or_async(
+        Self::create_sync_or_async(
             entity,
             topic,
             maybe_qos,
             Some(listener),
             ReaderType::Async(waker),
-        ) {
-            Ok(reader) => Ok(reader),
-            Err(e) => Err(e),
-        }
+            StatusSlots::new(),
+        )
     }
 
     /// read synchronously
-    pub fn read_now(&self, buf: &mut SampleBuffer<T>) -> Result<usize, DDSError> {
+    pub fn read_now(&self, buf: &mut SampleBuffer<T>) -> Result<ReadResult, DDSError> {
         Self::readn_from_entity_now(self.entity(), buf, false)
     }
 
     /// take synchronously
-    pub fn take_now(&self, buf: &mut SampleBuffer<T>) -> Result<usize, DDSError> {
+    pub fn take_now(&self, buf: &mut SampleBuffer<T>) -> Result<ReadResult, DDSError> {
         Self::readn_from_entity_now(self.entity(), buf, true)
     }
 
+    /// read synchronously the samples that match the filter
+    pub fn read_now_with(
+        &self,
+        buf: &mut SampleBuffer<T>,
+        filter: StateFilter,
+    ) -> Result<ReadResult, DDSError> {
+        Self::readn_from_entity_now_with(self.entity(), buf, false, filter)
+    }
+
+    /// take synchronously the samples that match the filter
+    pub fn take_now_with(
+        &self,
+        buf: &mut SampleBuffer<T>,
+        filter: StateFilter,
+    ) -> Result<ReadResult, DDSError> {
+        Self::readn_from_entity_now_with(self.entity(), buf, true, filter)
+    }
+
+    /// take up to `max` samples synchronously and move them out of the reader.
+    /// A sample is only cloned if it is still referenced elsewhere, such as by
+    /// another reader or by the history of a local writer.
+    pub fn take_owned(&self, max: usize) -> Result<Vec<T>, DDSError>
+    where
+        T: Clone,
+    {
+        let samples = self.take_owned_with_info(max)?;
+        Ok(samples
+            .into_iter()
+            .filter_map(|(sample, _)| sample)
+            .collect())
+    }
+
+    /// take up to `max` samples synchronously and move them out of the reader,
+    /// together with their sample info. Invalid samples are returned as `None`.
+    /// See `take_owned`.
+    pub fn take_owned_with_info(&self, max: usize) -> Result<Vec<(Option<T>, SampleInfo)>, DDSError>
+    where
+        T: Clone,
+    {
+        let mut buf = SampleBuffer::new(max);
+        Self::readn_from_entity_now(self.entity(), &mut buf, true)?;
+        Ok(buf.take_owned_with_info())
+    }
+
+    /// read up to `max` samples synchronously without copying them. The samples
+    /// stay on loan from the reader until the returned `LoanedSamples` are
+    /// dropped. Only available for fixed size types.
+    pub fn read_loan(&self, max: usize) -> Result<LoanedSamples<'_, T>, DDSError>
+    where
+        T: TopicFixedSize,
+    {
+        LoanedSamples::new(self, max, false)
+    }
+
+    /// take up to `max` samples synchronously without copying them. See `read_loan`.
+    pub fn take_loan(&self, max: usize) -> Result<LoanedSamples<'_, T>, DDSError>
+    where
+        T: TopicFixedSize,
+    {
+        LoanedSamples::new(self, max, true)
+    }
+
+    /// Find the instance of the given key. Returns None if the reader does not
+    /// know the instance.
+    ///
+    /// Cyclone has no read of the next instance, so there is no way to iterate
+    /// over the instances of a reader without changing the sample states.
+    /// Applications that need to visit instances one by one keep the keys they
+    /// are interested in and look up their handles.
+    pub fn lookup_instance(&self, key: &T::Key) -> Option<InstanceHandle> {
+        let sample = Sample::<T>::from_key_cdr(T::key_to_cdr(key));
+        let sample = &sample as *const Sample<T> as *const c_void;
+        let handle = unsafe { dds_lookup_instance(self.entity().entity(), sample) };
+        let handle = InstanceHandle::from(handle);
+        if handle.is_nil() {
+            None
+        } else {
+            Some(handle)
+        }
+    }
+
+    /// read synchronously the samples of one instance
+    pub fn read_instance(
+        &self,
+        buf: &mut SampleBuffer<T>,
+        instance: InstanceHandle,
+    ) -> Result<ReadResult, DDSError> {
+        Self::readn_instance_from_entity_now(self.entity(), buf, false, instance)
+    }
+
+    /// take synchronously the samples of one instance
+    pub fn take_instance(
+        &self,
+        buf: &mut SampleBuffer<T>,
+        instance: InstanceHandle,
+    ) -> Result<ReadResult, DDSError> {
+        Self::readn_instance_from_entity_now(self.entity(), buf, true, instance)
+    }
+
+    /// Read or take the samples of one instance synchronously. The buffer for the
+    /// samples must be passed in. On success, returns the number of valid and invalid
+    /// samples read.
+    pub fn readn_instance_from_entity_now(
+        entity: &DdsEntity,
+        buf: &mut SampleBuffer<T>,
+        take: bool,
+        instance: InstanceHandle,
+    ) -> Result<ReadResult, DDSError> {
+        let (voidp, info_ptr) = unsafe { buf.as_mut_ptr() };
+        let voidpp = voidp as *mut *mut c_void;
+
+        let ret = unsafe {
+            if take {
+                dds_take_instance(
+                    entity.entity(),
+                    voidpp,
+                    info_ptr,
+                    buf.len() as size_t,
+                    buf.len() as u32,
+                    instance.into(),
+                )
+            } else {
+                dds_read_instance(
+                    entity.entity(),
+                    voidpp,
+                    info_ptr,
+                    buf.len() as size_t,
+                    buf.len() as u32,
+                    instance.into(),
+                )
+            }
+        };
+        ReadResult::from_return(buf, ret)
+    }
+
     /// Read multiple samples from the reader synchronously. The buffer for the sampes must be passed in.
-    /// On success, returns the number of samples read.
+    /// On success, returns the number of valid and invalid samples read. Both are
+    /// zero if no samples were available.
     pub fn readn_from_entity_now(
         entity: &DdsEntity,
         buf: &mut SampleBuffer<T>,
         take: bool,
-    ) -> Result<usize, DDSError> {
+    ) -> Result<ReadResult, DDSError> {
+        Self::readn_from_entity_now_with(entity, buf, take, StateFilter::any())
+    }
+
+    /// Read multiple samples matching the filter from the reader synchronously.
+    /// On success, returns the number of valid and invalid samples read.
+    pub fn readn_from_entity_now_with(
+        entity: &DdsEntity,
+        buf: &mut SampleBuffer<T>,
+        take: bool,
+        filter: StateFilter,
+    ) -> Result<ReadResult, DDSError> {
         let (voidp, info_ptr) = unsafe { buf.as_mut_ptr() };
         let voidpp = voidp as *mut *mut c_void;
         //println!("Infoptr:{:?}",info_ptr);
 
         let ret = unsafe {
             if take {
-                dds_take(
+                dds_take_mask(
                     entity.entity(),
                     voidpp,
                     info_ptr as *mut _,
                     buf.len() as size_t,
                     buf.len() as u32,
+                    filter.mask(),
                 )
             } else {
-                dds_read(
+                dds_read_mask(
                     entity.entity(),
                     voidpp,
                     info_ptr as *mut _,
                     buf.len() as size_t,
                     buf.len() as u32,
+                    filter.mask(),
                 )
             }
         };
-        if ret > 0 {
-            // If first sample is value we assume all are
-            if buf.is_valid_sample(0) {
-                Ok(ret as usize)
-            } else {
-                Err(DDSError::NoData)
-            }
-        } else {
-            Err(DDSError::OutOfResources)
-        }
+        ReadResult::from_return(buf, ret)
+    }
+
+    /// Read samples asynchronously. Completes once valid or invalid samples were read.
+    pub async fn read(&self, samples: &mut SampleBuffer<T>) -> Result<ReadResult, ReaderError> {
+        self.read_with(samples, StateFilter::any()).await
     }
 
-    /// Read samples asynchronously. The number of samples actually read is returned.
-    pub async fn read(&self, samples: &mut SampleBuffer<T>) -> Result<usize, ReaderError> {
+    /// Get samples asynchronously. Completes once valid or invalid samples were taken.
+    pub async fn take(&self, samples: &mut SampleBuffer<T>) -> Result<ReadResult, ReaderError> {
+        self.take_with(samples, StateFilter::any()).await
+    }
+
+    /// Read samples matching the filter asynchronously.
+    pub async fn read_with(
+        &self,
+        samples: &mut SampleBuffer<T>,
+        filter: StateFilter,
+    ) -> Result<ReadResult, ReaderError> {
         if let ReaderType::Async(waker) = &self.inner.reader_type {
             let future_sample = SampleArrayFuture::new(
                 self.inner.entity.clone(),
                 waker.clone(),
                 samples,
                 FutureType::Read,
+                filter,
             );
             future_sample.await
         } else {
@@ -270,14 +474,19 @@ where
         }
     }
 
-    /// Get samples asynchronously. The number of samples actually read is returned.
-    pub async fn take(&self, samples: &mut SampleBuffer<T>) -> Result<usize, ReaderError> {
+    /// Get samples matching the filter asynchronously.
+    pub async fn take_with(
+        &self,
+        samples: &mut SampleBuffer<T>,
+        filter: StateFilter,
+    ) -> Result<ReadResult, ReaderError> {
         if let ReaderType::Async(waker) = &self.inner.reader_type {
             let future_sample = SampleArrayFuture::new(
                 self.inner.entity.clone(),
                 waker.clone(),
                 samples,
                 FutureType::Take,
+                filter,
             );
             future_sample.await
         } else {
@@ -285,12 +494,217 @@ where
         }
     }
 
-    pub fn create_readcondition(
-        &'a mut self,
-        mask: StateMask,
-    ) -> Result<DdsReadCondition<T>, DDSError> {
+    /// Blocking iterator over the instance events of this reader. The samples
+    /// of the reader are taken to produce the events.
+    pub fn instance_events(&self) -> Result<InstanceEvents<'_, T>, DDSError>
+    where
+        T: Clone,
+    {
+        InstanceEvents::new(self)
+    }
+
+    /// Stream of the instance events of this reader. The samples of the reader
+    /// are taken to produce the events. Only available on async readers.
+    pub fn instance_event_stream(&self) -> Result<InstanceEventStream<'_, T>, ReaderError>
+    where
+        T: Clone,
+    {
+        match self.async_waker() {
+            Some(waker) => Ok(InstanceEventStream::new(self, waker.clone())),
+            None => Err(ReaderError::ReaderNotAsync),
+        }
+    }
+
+    /// Stream of the samples of this reader. The samples are taken from the
+    /// reader `batch_size` at a time and moved out, they are only cloned when
+    /// another reader still holds them. The stream ends when the reader is
+    /// deleted, other failures are yielded as errors. Only available on async
+    /// readers.
+    pub fn stream(&self, batch_size: usize) -> Result<SampleStream<T>, ReaderError>
+    where
+        T: Clone,
+    {
+        match self.async_waker() {
+            Some(waker) => Ok(SampleStream::new(
+                self.entity().clone(),
+                waker.clone(),
+                batch_size,
+            )),
+            None => Err(ReaderError::ReaderNotAsync),
+        }
+    }
+
+    /// Like `stream`, but the samples are shared with the other readers of
+    /// the same data instead of cloned.
+    pub fn stream_shared(&self, batch_size: usize) -> Result<SharedSampleStream<T>, ReaderError> {
+        match self.async_waker() {
+            Some(waker) => Ok(SharedSampleStream::new(
+                self.entity().clone(),
+                waker.clone(),
+                batch_size,
+            )),
+            None => Err(ReaderError::ReaderNotAsync),
+        }
+    }
+
+    /// Like `stream`, but the samples are yielded with their sample info and
+    /// invalid samples are included.
+    pub fn stream_with_info(&self, batch_size: usize) -> Result<SampleInfoStream<T>, ReaderError>
+    where
+        T: Clone,
+    {
+        match self.async_waker() {
+            Some(waker) => Ok(SampleInfoStream::new(
+                self.entity().clone(),
+                waker.clone(),
+                batch_size,
+            )),
+            None => Err(ReaderError::ReaderNotAsync),
+        }
+    }
+
+    /// Get a status of this reader. The `_change` counts are the changes since
+    /// the status was last read with `reset` set, unless a listener given by the
+    /// application handles the status.
+    pub fn status<S: ReaderStatus>(&self, reset: bool) -> Result<S, DDSError> {
+        self.inner
+            .status_events
+            .lock()
+            .unwrap()
+            .read(self.entity(), reset)
+    }
+
+    /// Get the status of the writers matched with this reader. The change counts are reset.
+    pub fn subscription_matched_status(&self) -> Result<SubscriptionMatchedStatus, DDSError> {
+        self.status(true)
+    }
+
+    /// Get the status of the liveliness of the writers matched with this reader
+    pub fn liveliness_changed_status(
+        &self,
+        reset: bool,
+    ) -> Result<LivelinessChangedStatus, DDSError> {
+        self.status(reset)
+    }
+
+    /// Get the status of the samples lost by this reader
+    pub fn sample_lost_status(&self, reset: bool) -> Result<SampleLostStatus, DDSError> {
+        self.status(reset)
+    }
+
+    /// Get the status of the samples rejected by this reader
+    pub fn sample_rejected_status(&self, reset: bool) -> Result<SampleRejectedStatus, DDSError> {
+        self.status(reset)
+    }
+
+    /// Get the status of the deadlines missed by the instances of this reader
+    pub fn requested_deadline_missed_status(
+        &self,
+        reset: bool,
+    ) -> Result<RequestedDeadlineMissedStatus, DDSError> {
+        self.status(reset)
+    }
+
+    /// Get the status of the writers this reader could not match because of their QoS
+    pub fn requested_incompatible_qos_status(
+        &self,
+        reset: bool,
+    ) -> Result<RequestedIncompatibleQosStatus, DDSError> {
+        self.status(reset)
+    }
+
+    /// Get the remote writers currently matched with this reader
+    pub fn matched_publications(&self) -> Result<Vec<MatchedEndpoint>, DDSError> {
+        dds_api::matched_endpoints(
+            self.entity(),
+            dds_get_matched_publications,
+            dds_get_matched_publication_data,
+        )
+    }
+
+    /// Block until the historical data of a durable reader has been received.
+    /// Fails with `DDSError::Timeout` if this does not happen within `timeout`.
+    pub fn wait_for_historical_data(&self, timeout: Duration) -> Result<(), DDSError> {
+        wait_for_historical_data(self.entity(), timeout)
+    }
+
+    /// Wait asynchronously until the historical data of a durable reader has been
+    /// received. See `wait_for_historical_data`.
+    pub async fn wait_for_historical_data_async(&self, timeout: Duration) -> Result<(), DDSError> {
+        let entity = self.entity().clone();
+        BlockingFuture::new(move |cancel| {
+            wait_for_historical_data_until_cancelled(&entity, timeout, cancel)
+        })
+        .await
+    }
+
+    /// Stream of the changes of a status of this reader, such as
+    /// `SubscriptionMatchedStatus` or `RequestedDeadlineMissedStatus`. Fails with
+    /// `PreconditionNotMet` if the reader was created with a listener of its own.
+    ///
+    /// The first stream installs a listener recording the statuses of the
+    /// reader. From then on these statuses no longer trigger the status
+    /// condition of the reader and are not passed on to the listeners of the
+    /// subscriber and participant.
+    pub fn status_stream<S: ReaderStatus>(&self) -> Result<StatusStream<S>, DDSError> {
+        let events = &self.inner.status_events;
+        let base = || match &self.inner.reader_type {
+            ReaderType::Async(waker) => data_available_listener(waker),
+            ReaderType::Sync => DdsListener::new(),
+        };
+        dds_status_events::start_recording(
+            events,
+            self.entity(),
+            base,
+            dds_status_events::record_reader_statuses,
+        )?;
+        Ok(StatusStream::new(events.clone()))
+    }
+
+    /// Wait for the next change of a status of this reader. See `status_stream`.
+    pub async fn status_changed<S: ReaderStatus>(&self) -> Result<S, DDSError> {
+        dds_status_events::next_change(self.status_stream()?).await
+    }
+
+    // Number of entries for the buffer of a loan of `max` samples. Cyclone fills
+    // in a pointer for every sample of the loan it keeps, which may be larger.
+    pub(crate) fn loan_buffer_len(&self, max: usize) -> usize {
+        let previous = self.inner.loan_len.fetch_max(max, Ordering::Relaxed);
+        previous.max(max)
+    }
+
+    // the state shared by the reader and its conditions
+    pub(crate) fn shared(&self) -> Arc<Inner<T>> {
+        self.inner.clone()
+    }
+
+    // the waker of an async reader
+    pub(crate) fn async_waker(&self) -> Option<&ReaderWaker> {
+        match &self.inner.reader_type {
+            ReaderType::Async(waker) => Some(waker),
+            ReaderType::Sync => None,
+        }
+    }
+
+    /// Create a condition for the samples matching the mask
+    pub fn create_readcondition(&self, mask: StateMask) -> Result<DdsReadCondition<T>, DDSError> {
         DdsReadCondition::create(self, mask)
     }
+
+    /// Create a condition for the samples matching both the mask and the
+    /// predicate. The predicate is called by Cyclone as samples arrive.
+    /// No more than `MAX_QUERY_CONDITIONS` query conditions can exist at the
+    /// same time in the process. Creating more fails with `TooManyConditions`.
+    pub fn create_querycondition<F>(
+        &self,
+        mask: StateMask,
+        predicate: F,
+    ) -> Result<DdsQueryCondition<T>, QueryConditionError>
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'static,
+    {
+        DdsQueryCondition::create(self, mask, predicate)
+    }
 }
 
 impl<'a, T> Entity for DdsReader<T>
@@ -302,14 +716,55 @@ where
     }
 }
 
-impl<'a, T> Drop for DdsReader<T>
+fn wait_for_historical_data(entity: &DdsEntity, timeout: Duration) -> Result<(), DDSError> {
+    let timeout = i64::try_from(timeout.as_nanos()).unwrap_or(i64::MAX);
+    let ret = unsafe { dds_reader_wait_for_historical_data(entity.entity(), timeout) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(DDSError::from(ret))
+    }
+}
+
+// Wait for the historical data in steps of HISTORICAL_DATA_WAIT_STEP, so that
+// the wait ends soon after the future is dropped
+fn wait_for_historical_data_until_cancelled(
+    entity: &DdsEntity,
+    timeout: Duration,
+    cancel: &Cancel,
+) -> Result<(), DDSError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let wait = deadline.saturating_duration_since(Instant::now());
+        match wait_for_historical_data(entity, wait.min(HISTORICAL_DATA_WAIT_STEP)) {
+            Err(DDSError::Timeout) if wait > HISTORICAL_DATA_WAIT_STEP => {
+                if cancel.is_cancelled() {
+                    return Err(DDSError::Timeout);
+                }
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Longest single wait for historical data of an async wait
+const HISTORICAL_DATA_WAIT_STEP: Duration = Duration::from_millis(50);
+
+// The listener of an async reader, waking the tasks waiting for data
+fn data_available_listener(waker: &ReaderWaker) -> DdsListener {
+    let waker = waker.clone();
+    DdsListener::new().on_data_available(move |_entity| waker.lock().unwrap().wake_all())
+}
+
+// The reader is deleted when the reader and all its conditions are gone
+impl<T> Drop for Inner<T>
 where
     T: Sized + TopicType,
 {
     fn drop(&mut self) {
         unsafe {
-            //println!("Drop reader:{:?}", self.entity().entity());
-            let ret: DDSError = cyclonedds_sys::dds_delete(self.inner.entity.entity()).into();
+            //println!("Drop reader:{:?}", self.entity.entity());
+            let ret: DDSError = cyclonedds_sys::dds_delete(self.entity.entity()).into();
             if DDSError::DdsOk != ret {
                 //panic!("cannot delete Reader: {}", ret);
                 println!("Ignoring dds_delete failure for DdsReader");
@@ -317,34 +772,68 @@ where
                 //println!("Reader dropped");
             }
         }
+        // wake the waiting streams so they see the reader is gone
+        if let ReaderType::Async(waker) = &self.reader_type {
+            waker.lock().unwrap().close();
+        }
+        self.status_events.lock().unwrap().close();
     }
 }
 
-pub struct DdsReadCondition<'a, T: Sized + TopicType>(DdsEntity, &'a DdsReader<T>);
+/// A condition on the sample, view and instance states of the samples of a
+/// reader. It can be attached to a `DdsWaitset`, and the matching samples can
+/// be read or taken through it. The condition keeps the reader alive and is
+/// deleted on drop.
+pub struct DdsReadCondition<T: Sized + TopicType> {
+    entity: DdsEntity,
+    _reader: Arc<Inner<T>>,
+}
 
-impl<'a, T> DdsReadCondition<'a, T>
+impl<T> DdsReadCondition<T>
 where
     T: Sized + TopicType,
 {
-    fn create(reader: &'a DdsReader<T>, mask: StateMask) -> Result<Self, DDSError> {
+    fn create(reader: &DdsReader<T>, mask: StateMask) -> Result<Self, DDSError> {
         unsafe {
             let mask: u32 = *mask;
             let p = cyclonedds_sys::dds_create_readcondition(reader.entity().entity(), mask);
             if p > 0 {
-                Ok(DdsReadCondition(DdsEntity::new(p), reader))
+                Ok(DdsReadCondition {
+                    entity: DdsEntity::new(p),
+                    _reader: reader.inner.clone(),
+                })
             } else {
                 Err(DDSError::from(p))
             }
         }
     }
+
+    /// read the samples matching the condition synchronously
+    pub fn read_now(&self, buf: &mut SampleBuffer<T>) -> Result<ReadResult, DDSError> {
+        DdsReader::<T>::readn_from_entity_now(&self.entity, buf, false)
+    }
+
+    /// take the samples matching the condition synchronously
+    pub fn take_now(&self, buf: &mut SampleBuffer<T>) -> Result<ReadResult, DDSError> {
+        DdsReader::<T>::readn_from_entity_now(&self.entity, buf, true)
+    }
 }
 
-impl<'a, T> Entity for DdsReadCondition<'a, T>
+impl<T> Entity for DdsReadCondition<T>
 where
     T: std::marker::Sized + TopicType,
 {
     fn entity(&self) -> &DdsEntity {
-        &self.0
+        &self.entity
+    }
+}
+
+impl<T> Drop for DdsReadCondition<T>
+where
+    T: Sized + TopicType,
+{
+    fn drop(&mut self) {
+        unsafe { cyclonedds_sys::dds_delete(self.entity.entity()) };
     }
 }
 
@@ -364,23 +853,26 @@ impl FutureType {
 
 struct SampleArrayFuture<'a, T> {
     entity: DdsEntity,
-    waker: Arc<Mutex<(Option<Waker>, Result<(), crate::error::ReaderError>)>>,
+    waker: ReaderWaker,
     take_or_read: FutureType,
     buffer: &'a mut SampleBuffer<T>,
+    filter: StateFilter,
 }
 
 impl<'a, T> SampleArrayFuture<'a, T> {
     fn new(
         entity: DdsEntity,
-        waker: Arc<Mutex<(Option<Waker>, Result<(), crate::error::ReaderError>)>>,
+        waker: ReaderWaker,
         buffer: &'a mut SampleBuffer<T>,
         ty: FutureType,
+        filter: StateFilter,
     ) -> Self {
         Self {
             entity,
             waker,
             take_or_read: ty,
             buffer,
+            filter,
         }
     }
 }
@@ -389,7 +881,7 @@ impl<'a, T> Future for SampleArrayFuture<'a, T>
 where
     T: TopicType,
 {
-    type Output = Result<usize, ReaderError>;
+    type Output = Result<ReadResult, ReaderError>;
 
     fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
         // Lock the waker first in case a callback for read complete happens and we miss it
@@ -398,18 +890,15 @@ where
         let mut waker = waker.lock().unwrap();
         let is_take = self.take_or_read.is_take();
         let entity = self.entity.clone();
+        let filter = self.filter;
 
-        // check if we have an error from any of the callbacks
-        if let Err(e) = &waker.1 {
-            return Poll::Ready(Err(e.clone()));
-        }
-
-        match DdsReader::<T>::readn_from_entity_now(&entity, &mut self.buffer, is_take) {
-            Ok(len) => Poll::Ready(Ok(len)),
-            Err(DDSError::NoData) | Err(DDSError::OutOfResources) => {
-                let _ = waker.0.replace(ctx.waker().clone());
+        match DdsReader::<T>::readn_from_entity_now_with(&entity, &mut self.buffer, is_take, filter)
+        {
+            Ok(result) if result.is_empty() => {
+                waker.register(ctx.waker());
                 Poll::Pending
             }
+            Ok(result) => Poll::Ready(Ok(result)),
             Err(e) => {
                 //println!("Error:{}",e);
                 // Some other error happened
@@ -419,16 +908,41 @@ where
     }
 }
 
+#[cfg(test)]
+impl<T> DdsReader<T>
+where
+    T: Sized + TopicType,
+{
+    /// Wait for at most `timeout` until the reader has samples that were not
+    /// read. Returns false on timeout.
+    pub(crate) fn wait_for_unread(
+        &self,
+        participant: &crate::DdsParticipant,
+        timeout: Duration,
+    ) -> bool {
+        let unread = self
+            .create_readcondition(StateMask::from(State::DdsNotReadSampleState))
+            .unwrap();
+        let mut waitset = crate::DdsWaitset::<()>::create(participant).unwrap();
+        waitset.attach(&unread, &()).unwrap();
+        let mut triggered = Vec::with_capacity(1);
+        let timeout = i64::try_from(timeout.as_nanos()).unwrap_or(i64::MAX);
+        !waitset.wait(&mut triggered, timeout).unwrap().is_empty()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use core::panic;
-    use std::time::Duration;
 
     use super::*;
-    use crate::{DdsParticipant, DdsSubscriber};
+    use crate::dds_status::{PublicationMatchedStatus, SampleRejectedReason};
+    use crate::serdes::{InstanceState, SampleState, ViewState};
+    use crate::{DdsParticipant, DdsSubscriber, DdsWaitset};
     use crate::{DdsPublisher, DdsWriter};
 
     use cdds_derive::Topic;
+    use futures::StreamExt;
     use serde_derive::{Deserialize, Serialize};
     use tokio::runtime::Runtime;
 
@@ -470,7 +984,7 @@ mod test {
         }
     }
 
-    #[derive(Serialize, Deserialize, Topic, Debug, PartialEq)]
+    #[derive(Serialize, Deserialize, Topic, Debug, PartialEq, Clone)]
     struct AnotherTopic {
         pub value: u32,
         pub name: String,
@@ -526,7 +1040,7 @@ mod test {
             let _another_task = tokio::spawn(async move {
                 let mut samples = AnotherTopic::create_sample_buffer(5);
                 if let Ok(t) = another_reader.read(&mut samples).await {
-                    assert_eq!(t, 1);
+                    assert_eq!(t.valid, 1);
                     for s in samples.iter() {
                         println!("Got sample {}", s.key);
                     }
@@ -547,6 +1061,446 @@ mod test {
             tokio::time::sleep(std::time::Duration::from_millis(300)).await;
         });
     }
+    #[test]
+    fn test_sample_info() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            AnotherTopic::create_topic(&participant, Some("sample_info"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = DdsReader::create(&subscriber, topic, None, None).unwrap();
+
+        let before = std::time::SystemTime::now();
+        writer.write(Arc::new(AnotherTopic::default())).unwrap();
+
+        let mut samples = AnotherTopic::create_sample_buffer(4);
+        assert_eq!(reader.take_now(&mut samples).unwrap().valid, 1);
+        let infos: Vec<_> = samples.iter_with_info().collect();
+        assert_eq!(infos.len(), 1);
+        let (sample, info) = infos[0];
+        assert_eq!(sample, Some(&AnotherTopic::default()));
+        assert!(info.valid_data());
+        assert_eq!(info.sample_state(), SampleState::NotRead);
+        assert_eq!(info.view_state(), ViewState::New);
+        assert_eq!(info.instance_state(), InstanceState::Alive);
+        assert!(info.source_timestamp() >= before - Duration::from_secs(1));
+        assert!(!info.instance_handle().is_nil());
+        assert!(!info.publication_handle().is_nil());
+        let instance = info.instance_handle();
+
+        // Deleting the writer disposes the instance, which is delivered
+        // as an invalid sample.
+        drop(writer);
+        let mut disposed = None;
+        for _ in 0..50 {
+            let result = reader.take_now(&mut samples).unwrap();
+            if let Some((sample, info)) = samples.iter_with_info().next() {
+                assert_eq!(
+                    result,
+                    ReadResult {
+                        valid: 0,
+                        invalid: 1
+                    }
+                );
+                disposed = Some((sample.is_some(), *info));
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let (has_data, info) = disposed.expect("no dispose notification");
+        assert!(!has_data);
+        assert!(!info.valid_data());
+        assert_eq!(info.instance_state(), InstanceState::Disposed);
+        assert_eq!(info.instance_handle(), instance);
+    }
+
+    #[test]
+    fn test_state_filter() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            AnotherTopic::create_topic(&participant, Some("state_filter"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = DdsReader::create_async(&subscriber, topic, None).unwrap();
+
+        let sample = |key| {
+            Arc::new(AnotherTopic {
+                key,
+                ..Default::default()
+            })
+        };
+        writer.write(sample(1)).unwrap();
+        writer.write(sample(2)).unwrap();
+
+        let mut samples = AnotherTopic::create_sample_buffer(5);
+        let unread = StateFilter::not_read().alive();
+        assert_eq!(reader.read_now_with(&mut samples, unread).unwrap().valid, 2);
+        // everything has been read now
+        assert!(reader
+            .read_now_with(&mut samples, unread)
+            .unwrap()
+            .is_empty());
+
+        writer.write(sample(3)).unwrap();
+        let rt = Runtime::new().unwrap();
+        let n = rt.block_on(reader.read_with(&mut samples, unread)).unwrap();
+        assert_eq!(n.valid, 1);
+        assert_eq!(samples.iter().next().unwrap().key, 3);
+
+        let n = rt
+            .block_on(reader.take_with(&mut samples, StateFilter::read()))
+            .unwrap();
+        assert_eq!(n.valid, 3);
+        assert!(reader.take_now(&mut samples).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_instances() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            AnotherTopic::create_topic(&participant, Some("instances"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = DdsReader::create(&subscriber, topic, None, None).unwrap();
+
+        for key in 1..=3 {
+            writer
+                .write(Arc::new(AnotherTopic {
+                    key,
+                    value: key * 10,
+                    ..Default::default()
+                }))
+                .unwrap();
+        }
+
+        assert!(reader
+            .lookup_instance(&AnotherTopicKeyHolder_ { key: 9 })
+            .is_none());
+        let instance = reader
+            .lookup_instance(&AnotherTopicKeyHolder_ { key: 2 })
+            .unwrap();
+
+        let mut samples = AnotherTopic::create_sample_buffer(4);
+        assert_eq!(
+            reader.read_instance(&mut samples, instance).unwrap().valid,
+            1
+        );
+        assert_eq!(samples.iter().next().unwrap().value, 20);
+        assert_eq!(samples.info(0).instance_handle(), instance);
+
+        assert_eq!(
+            reader.take_instance(&mut samples, instance).unwrap().valid,
+            1
+        );
+        assert!(reader
+            .read_instance(&mut samples, instance)
+            .unwrap()
+            .is_empty());
+        // the samples of the other instances are left unread
+        let n = reader
+            .read_now_with(&mut samples, StateFilter::not_read())
+            .unwrap();
+        assert_eq!(n.valid, 2);
+    }
+
+    #[test]
+    fn test_concurrent_waiters() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic = AnotherTopic::create_topic(&participant, Some("waiters"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = Arc::new(DdsReader::create_async(&subscriber, topic, None).unwrap());
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            // every reading task sees the sample
+            let readers: Vec<_> = (0..4)
+                .map(|_| {
+                    let reader = reader.clone();
+                    tokio::spawn(async move {
+                        let mut samples = AnotherTopic::create_sample_buffer(1);
+                        reader.read(&mut samples).await.unwrap().valid
+                    })
+                })
+                .collect();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            writer.write(Arc::new(AnotherTopic::default())).unwrap();
+            for task in readers {
+                let read = tokio::time::timeout(Duration::from_secs(1), task)
+                    .await
+                    .expect("reader task was not woken")
+                    .unwrap();
+                assert_eq!(read, 1);
+            }
+            let mut samples = AnotherTopic::create_sample_buffer(1);
+            reader.take(&mut samples).await.unwrap();
+
+            // every sample is taken by exactly one task
+            let takers: Vec<_> = (0..4)
+                .map(|_| {
+                    let reader = reader.clone();
+                    tokio::spawn(async move {
+                        let mut samples = AnotherTopic::create_sample_buffer(1);
+                        reader.take(&mut samples).await.unwrap();
+                        let key = samples.iter().next().unwrap().key;
+                        key
+                    })
+                })
+                .collect();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            for key in 0..4 {
+                writer
+                    .write(Arc::new(AnotherTopic {
+                        key,
+                        ..Default::default()
+                    }))
+                    .unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            let mut keys = Vec::new();
+            for task in takers {
+                let key = tokio::time::timeout(Duration::from_secs(1), task)
+                    .await
+                    .expect("taking task was not woken")
+                    .unwrap();
+                keys.push(key);
+            }
+            keys.sort_unstable();
+            assert_eq!(keys, vec![0, 1, 2, 3]);
+        });
+    }
+
+    #[test]
+    fn test_status_events() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            AnotherTopic::create_topic(&participant, Some("status_events"), None, None).unwrap();
+        let mut qos = DdsQos::create().unwrap();
+        qos.set_deadline(Duration::from_millis(100));
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer =
+            DdsWriter::create(&publisher, topic.clone(), Some(qos.clone()), None).unwrap();
+        let mut publications = writer.status_stream::<PublicationMatchedStatus>().unwrap();
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+            let reader = DdsReader::create_async(&subscriber, topic, Some(qos)).unwrap();
+            let matched = tokio::time::timeout(Duration::from_secs(1), publications.next())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(matched.current_count, 1);
+
+            // the instance misses its deadline after the only write
+            writer.write(Arc::new(AnotherTopic::default())).unwrap();
+            let missed = tokio::time::timeout(
+                Duration::from_secs(1),
+                reader.status_changed::<RequestedDeadlineMissedStatus>(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            assert!(missed.total_count >= 1);
+
+            // a missed deadline does not fail the reads
+            let mut samples = AnotherTopic::create_sample_buffer(1);
+            assert_eq!(reader.take(&mut samples).await.unwrap().valid, 1);
+        });
+    }
+
+    #[test]
+    fn test_status_condition_without_stream() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            AnotherTopic::create_topic(&participant, Some("status_condition"), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = DdsReader::create(&subscriber, topic.clone(), None, None).unwrap();
+        let mut waitset = DdsWaitset::<()>::create(&participant).unwrap();
+        waitset.attach(&reader, &()).unwrap();
+
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let _writer = DdsWriter::create(&publisher, topic, None, None).unwrap();
+
+        // no listener handles the match, so the status condition triggers
+        let mut triggered = Vec::with_capacity(1);
+        assert_eq!(
+            waitset.wait(&mut triggered, 1_000_000_000).unwrap().len(),
+            1
+        );
+        let changes = dds_api::dds_get_status_changes(reader.entity()).unwrap();
+        assert!(changes.is_set(dds_api::DDS_SUBSCRIPTION_MATCHED_STATUS_ID));
+    }
+
+    #[test]
+    fn test_status_getters() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            AnotherTopic::create_topic(&participant, Some("status_getters"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let mut qos = DdsQos::create().unwrap();
+        qos.set_history(dds_history_kind::DDS_HISTORY_KEEP_ALL, 0)
+            .set_resource_limits(1, -1, -1);
+        let reader = DdsReader::create(&subscriber, topic, Some(qos), None).unwrap();
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(writer.wait_for_readers(1, Duration::from_secs(1)))
+            .unwrap();
+
+        // the second sample does not fit in the reader
+        writer.write(Arc::new(AnotherTopic::default())).unwrap();
+        writer.write(Arc::new(AnotherTopic::default())).unwrap();
+
+        let rejected = reader.sample_rejected_status(false).unwrap();
+        assert_eq!(rejected.total_count, 1);
+        assert_eq!(rejected.last_reason, SampleRejectedReason::SamplesLimit);
+        // the changes are kept until a read resets them
+        assert_eq!(
+            reader
+                .sample_rejected_status(true)
+                .unwrap()
+                .total_count_change,
+            1
+        );
+        let rejected = reader.sample_rejected_status(true).unwrap();
+        assert_eq!((rejected.total_count, rejected.total_count_change), (1, 0));
+
+        assert_eq!(reader.sample_lost_status(true).unwrap().total_count, 0);
+        let matched: SubscriptionMatchedStatus = reader.status(false).unwrap();
+        assert_eq!(matched.current_count, 1);
+    }
+
+    #[test]
+    fn test_take_owned() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            AnotherTopic::create_topic(&participant, Some("take_owned"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let mut qos = DdsQos::create().unwrap();
+        qos.set_history(dds_history_kind::DDS_HISTORY_KEEP_LAST, 10);
+        let reader = DdsReader::create(&subscriber, topic, Some(qos), None).unwrap();
+
+        let kept = Arc::new(AnotherTopic {
+            value: 1,
+            name: "kept".to_owned(),
+            ..Default::default()
+        });
+        writer.write(kept.clone()).unwrap();
+        let moved = Arc::new(AnotherTopic {
+            value: 2,
+            name: "moved".to_owned(),
+            ..Default::default()
+        });
+        let moved_name = moved.name.as_ptr();
+        writer.write(moved).unwrap();
+
+        let samples = reader.take_owned_with_info(4).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert!(samples.iter().all(|(_, info)| info.valid_data()));
+        let mut names: Vec<_> = samples
+            .into_iter()
+            .map(|(sample, _)| sample.unwrap().name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["kept".to_owned(), "moved".to_owned()]);
+        // the sample still referenced by the application was cloned, the other
+        // one kept its heap allocation
+        assert_ne!(names[0].as_ptr(), kept.name.as_ptr());
+        assert_eq!(names[1].as_ptr(), moved_name);
+
+        assert!(reader.take_owned(4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_readcondition() {
+        struct Monitor {
+            reader: DdsReader<AnotherTopic>,
+            unread: DdsReadCondition<AnotherTopic>,
+        }
+
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            AnotherTopic::create_topic(&participant, Some("readcondition"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let mut qos = DdsQos::create().unwrap();
+        qos.set_history(dds_history_kind::DDS_HISTORY_KEEP_LAST, 10);
+        let reader = DdsReader::create(&subscriber, topic, Some(qos), None).unwrap();
+        let unread = reader
+            .create_readcondition(StateMask::from(State::DdsNotReadSampleState))
+            .unwrap();
+        let monitor = Monitor { reader, unread };
+
+        let mut waitset = DdsWaitset::<()>::create(&participant).unwrap();
+        waitset.attach(&monitor.unread, &()).unwrap();
+        writer.write(Arc::new(AnotherTopic::default())).unwrap();
+        let mut triggered = Vec::with_capacity(1);
+        assert_eq!(
+            waitset.wait(&mut triggered, 1_000_000_000).unwrap().len(),
+            1
+        );
+
+        // the reader can be read while the condition exists
+        let mut samples = AnotherTopic::create_sample_buffer(2);
+        assert_eq!(monitor.reader.read_now(&mut samples).unwrap().valid, 1);
+        assert!(monitor.unread.read_now(&mut samples).unwrap().is_empty());
+
+        // the condition keeps the reader alive
+        let Monitor { reader, unread } = monitor;
+        drop(reader);
+        writer.write(Arc::new(AnotherTopic::default())).unwrap();
+        assert_eq!(unread.take_now(&mut samples).unwrap().valid, 1);
+    }
+
+    #[test]
+    fn test_matched_publications() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            AnotherTopic::create_topic(&participant, Some("matched_publications"), None, None)
+                .unwrap();
+        let partition = std::ffi::CString::new("feeders").unwrap();
+        let mut writer_qos = DdsQos::create().unwrap();
+        writer_qos
+            .set_partition(&partition)
+            .set_ownership(dds_ownership_kind::DDS_OWNERSHIP_EXCLUSIVE)
+            .set_ownership_strength(7);
+        let mut reader_qos = DdsQos::create().unwrap();
+        reader_qos
+            .set_partition(&partition)
+            .set_ownership(dds_ownership_kind::DDS_OWNERSHIP_EXCLUSIVE);
+
+        let publisher = DdsPublisher::create(&participant, Some(writer_qos.clone()), None).unwrap();
+        let writer = DdsWriter::create(&publisher, topic.clone(), Some(writer_qos), None).unwrap();
+        let subscriber =
+            DdsSubscriber::create(&participant, Some(reader_qos.clone()), None).unwrap();
+        let reader = DdsReader::create(&subscriber, topic, Some(reader_qos), None).unwrap();
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(writer.wait_for_readers(1, Duration::from_secs(1)))
+            .unwrap();
+        let status = reader.subscription_matched_status().unwrap();
+        assert_eq!(status.current_count, 1);
+
+        let matched = reader.matched_publications().unwrap();
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].partitions, vec!["feeders".to_owned()]);
+        assert_eq!(matched[0].ownership_strength, Some(7));
+        // both endpoints belong to the same participant
+        assert_eq!(
+            matched[0].participant_guid,
+            writer.matched_subscriptions().unwrap()[0].participant_guid
+        );
+    }
+
     /*
         #[test]
         fn test_requested_deadline_miss() {
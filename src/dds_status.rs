@@ -0,0 +1,553 @@
+/*
+    Copyright 2021 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Rust representations of the DDS communication statuses.
+
+use std::convert::From;
+
+use crate::dds_api::*;
+use cyclonedds_sys::*;
+
+/// A communication status of an entity
+pub trait Status: Copy + Send + 'static {
+    /// The Cyclone id of the status
+    const ID: dds_status_id;
+
+    /// Get the status of an entity. Cyclone resets the change counts.
+    #[doc(hidden)]
+    fn get(entity: &DdsEntity) -> Result<Self, DDSError>;
+
+    /// Add the change counts of an earlier value of the status
+    #[doc(hidden)]
+    fn add_changes(&mut self, earlier: &Self);
+}
+
+// implement Status with the Cyclone getter of the status
+macro_rules! impl_status {
+    ($status:ident, $id:ident, $raw:ident, $get:ident, $($change:ident),+) => {
+        impl Status for $status {
+            const ID: dds_status_id = $id;
+
+            fn get(entity: &DdsEntity) -> Result<Self, DDSError> {
+                let mut status = $raw::default();
+                let ret = unsafe { $get(entity.entity(), &mut status) };
+                if ret == 0 {
+                    Ok(status.into())
+                } else {
+                    Err(DDSError::from(ret))
+                }
+            }
+
+            fn add_changes(&mut self, earlier: &Self) {
+                $(self.$change += earlier.$change;)+
+            }
+        }
+    };
+}
+
+/// A status of a reader
+pub trait ReaderStatus: Status {}
+
+/// A status of a writer
+pub trait WriterStatus: Status {}
+
+/// Status of the readers matched with a writer
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PublicationMatchedStatus {
+    /// Total number of readers ever matched
+    pub total_count: u32,
+    pub total_count_change: i32,
+    /// Number of readers currently matched
+    pub current_count: u32,
+    pub current_count_change: i32,
+    /// The reader that last matched or unmatched
+    pub last_subscription_handle: InstanceHandle,
+}
+
+impl From<dds_publication_matched_status_t> for PublicationMatchedStatus {
+    fn from(status: dds_publication_matched_status_t) -> Self {
+        Self {
+            total_count: status.total_count,
+            total_count_change: status.total_count_change,
+            current_count: status.current_count,
+            current_count_change: status.current_count_change,
+            last_subscription_handle: status.last_subscription_handle.into(),
+        }
+    }
+}
+
+impl_status!(
+    PublicationMatchedStatus,
+    DDS_PUBLICATION_MATCHED_STATUS_ID,
+    dds_publication_matched_status_t,
+    dds_get_publication_matched_status,
+    total_count_change,
+    current_count_change
+);
+impl WriterStatus for PublicationMatchedStatus {}
+
+/// Status of the writers matched with a reader
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriptionMatchedStatus {
+    /// Total number of writers ever matched
+    pub total_count: u32,
+    pub total_count_change: i32,
+    /// Number of writers currently matched
+    pub current_count: u32,
+    pub current_count_change: i32,
+    /// The writer that last matched or unmatched
+    pub last_publication_handle: InstanceHandle,
+}
+
+impl From<dds_subscription_matched_status_t> for SubscriptionMatchedStatus {
+    fn from(status: dds_subscription_matched_status_t) -> Self {
+        Self {
+            total_count: status.total_count,
+            total_count_change: status.total_count_change,
+            current_count: status.current_count,
+            current_count_change: status.current_count_change,
+            last_publication_handle: status.last_publication_handle.into(),
+        }
+    }
+}
+
+impl_status!(
+    SubscriptionMatchedStatus,
+    DDS_SUBSCRIPTION_MATCHED_STATUS_ID,
+    dds_subscription_matched_status_t,
+    dds_get_subscription_matched_status,
+    total_count_change,
+    current_count_change
+);
+impl ReaderStatus for SubscriptionMatchedStatus {}
+
+/// Status of the liveliness of the writers matched with a reader
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LivelinessChangedStatus {
+    /// Number of matched writers that are alive
+    pub alive_count: u32,
+    /// Number of matched writers that are not alive
+    pub not_alive_count: u32,
+    pub alive_count_change: i32,
+    pub not_alive_count_change: i32,
+    /// The writer whose liveliness last changed
+    pub last_publication_handle: InstanceHandle,
+}
+
+impl From<dds_liveliness_changed_status_t> for LivelinessChangedStatus {
+    fn from(status: dds_liveliness_changed_status_t) -> Self {
+        Self {
+            alive_count: status.alive_count,
+            not_alive_count: status.not_alive_count,
+            alive_count_change: status.alive_count_change,
+            not_alive_count_change: status.not_alive_count_change,
+            last_publication_handle: status.last_publication_handle.into(),
+        }
+    }
+}
+
+impl_status!(
+    LivelinessChangedStatus,
+    DDS_LIVELINESS_CHANGED_STATUS_ID,
+    dds_liveliness_changed_status_t,
+    dds_get_liveliness_changed_status,
+    alive_count_change,
+    not_alive_count_change
+);
+impl ReaderStatus for LivelinessChangedStatus {}
+
+/// Status of a writer that failed to assert its liveliness
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LivelinessLostStatus {
+    /// Number of times liveliness was lost
+    pub total_count: u32,
+    pub total_count_change: i32,
+}
+
+impl From<dds_liveliness_lost_status_t> for LivelinessLostStatus {
+    fn from(status: dds_liveliness_lost_status_t) -> Self {
+        Self {
+            total_count: status.total_count,
+            total_count_change: status.total_count_change,
+        }
+    }
+}
+
+impl_status!(
+    LivelinessLostStatus,
+    DDS_LIVELINESS_LOST_STATUS_ID,
+    dds_liveliness_lost_status_t,
+    dds_get_liveliness_lost_status,
+    total_count_change
+);
+impl WriterStatus for LivelinessLostStatus {}
+
+/// Status of the samples a reader lost before receiving them
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SampleLostStatus {
+    /// Number of samples lost
+    pub total_count: u32,
+    pub total_count_change: i32,
+}
+
+impl From<dds_sample_lost_status_t> for SampleLostStatus {
+    fn from(status: dds_sample_lost_status_t) -> Self {
+        Self {
+            total_count: status.total_count,
+            total_count_change: status.total_count_change,
+        }
+    }
+}
+
+impl_status!(
+    SampleLostStatus,
+    DDS_SAMPLE_LOST_STATUS_ID,
+    dds_sample_lost_status_t,
+    dds_get_sample_lost_status,
+    total_count_change
+);
+impl ReaderStatus for SampleLostStatus {}
+
+/// Why a reader rejected a sample
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SampleRejectedReason {
+    #[default]
+    NotRejected,
+    /// The resource limit on the number of instances was reached
+    InstancesLimit,
+    /// The resource limit on the number of samples was reached
+    SamplesLimit,
+    /// The resource limit on the number of samples per instance was reached
+    SamplesPerInstanceLimit,
+}
+
+impl From<dds_sample_rejected_status_kind> for SampleRejectedReason {
+    fn from(kind: dds_sample_rejected_status_kind) -> Self {
+        if kind == dds_sample_rejected_status_kind_DDS_REJECTED_BY_INSTANCES_LIMIT {
+            SampleRejectedReason::InstancesLimit
+        } else if kind == dds_sample_rejected_status_kind_DDS_REJECTED_BY_SAMPLES_LIMIT {
+            SampleRejectedReason::SamplesLimit
+        } else if kind == dds_sample_rejected_status_kind_DDS_REJECTED_BY_SAMPLES_PER_INSTANCE_LIMIT
+        {
+            SampleRejectedReason::SamplesPerInstanceLimit
+        } else {
+            SampleRejectedReason::NotRejected
+        }
+    }
+}
+
+/// Status of the samples a reader rejected
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SampleRejectedStatus {
+    /// Number of samples rejected
+    pub total_count: u32,
+    pub total_count_change: i32,
+    /// Why the last sample was rejected
+    pub last_reason: SampleRejectedReason,
+    /// The instance of the last rejected sample
+    pub last_instance_handle: InstanceHandle,
+}
+
+impl From<dds_sample_rejected_status_t> for SampleRejectedStatus {
+    fn from(status: dds_sample_rejected_status_t) -> Self {
+        Self {
+            total_count: status.total_count,
+            total_count_change: status.total_count_change,
+            last_reason: status.last_reason.into(),
+            last_instance_handle: status.last_instance_handle.into(),
+        }
+    }
+}
+
+impl_status!(
+    SampleRejectedStatus,
+    DDS_SAMPLE_REJECTED_STATUS_ID,
+    dds_sample_rejected_status_t,
+    dds_get_sample_rejected_status,
+    total_count_change
+);
+impl ReaderStatus for SampleRejectedStatus {}
+
+// The values of dds_qos_policy_id_t in dds/ddsc/dds_public_qosdefs.h. The
+// cyclonedds-sys 0.2 bindings have no constants for them, so they are
+// redefined here to map them to QosPolicyId and must be kept in sync with the
+// header.
+pub(crate) const DDS_INVALID_QOS_POLICY_ID: u32 = 0;
+pub(crate) const DDS_USERDATA_QOS_POLICY_ID: u32 = 1;
+pub(crate) const DDS_DURABILITY_QOS_POLICY_ID: u32 = 2;
+pub(crate) const DDS_PRESENTATION_QOS_POLICY_ID: u32 = 3;
+pub(crate) const DDS_DEADLINE_QOS_POLICY_ID: u32 = 4;
+pub(crate) const DDS_LATENCYBUDGET_QOS_POLICY_ID: u32 = 5;
+pub(crate) const DDS_OWNERSHIP_QOS_POLICY_ID: u32 = 6;
+pub(crate) const DDS_OWNERSHIPSTRENGTH_QOS_POLICY_ID: u32 = 7;
+pub(crate) const DDS_LIVELINESS_QOS_POLICY_ID: u32 = 8;
+pub(crate) const DDS_TIMEBASEDFILTER_QOS_POLICY_ID: u32 = 9;
+pub(crate) const DDS_PARTITION_QOS_POLICY_ID: u32 = 10;
+pub(crate) const DDS_RELIABILITY_QOS_POLICY_ID: u32 = 11;
+pub(crate) const DDS_DESTINATIONORDER_QOS_POLICY_ID: u32 = 12;
+pub(crate) const DDS_HISTORY_QOS_POLICY_ID: u32 = 13;
+pub(crate) const DDS_RESOURCELIMITS_QOS_POLICY_ID: u32 = 14;
+pub(crate) const DDS_ENTITYFACTORY_QOS_POLICY_ID: u32 = 15;
+pub(crate) const DDS_WRITERDATALIFECYCLE_QOS_POLICY_ID: u32 = 16;
+pub(crate) const DDS_READERDATALIFECYCLE_QOS_POLICY_ID: u32 = 17;
+pub(crate) const DDS_TOPICDATA_QOS_POLICY_ID: u32 = 18;
+pub(crate) const DDS_GROUPDATA_QOS_POLICY_ID: u32 = 19;
+pub(crate) const DDS_TRANSPORTPRIORITY_QOS_POLICY_ID: u32 = 20;
+pub(crate) const DDS_LIFESPAN_QOS_POLICY_ID: u32 = 21;
+pub(crate) const DDS_DURABILITYSERVICE_QOS_POLICY_ID: u32 = 22;
+pub(crate) const DDS_PROPERTY_QOS_POLICY_ID: u32 = 23;
+pub(crate) const DDS_TYPE_CONSISTENCY_ENFORCEMENT_QOS_POLICY_ID: u32 = 24;
+pub(crate) const DDS_DATA_REPRESENTATION_QOS_POLICY_ID: u32 = 25;
+
+/// A QoS policy, as reported by the incompatible QoS statuses
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum QosPolicyId {
+    #[default]
+    Invalid,
+    UserData,
+    Durability,
+    Presentation,
+    Deadline,
+    LatencyBudget,
+    Ownership,
+    OwnershipStrength,
+    Liveliness,
+    TimeBasedFilter,
+    Partition,
+    Reliability,
+    DestinationOrder,
+    History,
+    ResourceLimits,
+    EntityFactory,
+    WriterDataLifecycle,
+    ReaderDataLifecycle,
+    TopicData,
+    GroupData,
+    TransportPriority,
+    Lifespan,
+    DurabilityService,
+    Property,
+    TypeConsistencyEnforcement,
+    DataRepresentation,
+    /// A policy id this version does not know
+    Unknown(u32),
+}
+
+impl From<u32> for QosPolicyId {
+    fn from(id: u32) -> Self {
+        match id {
+            DDS_INVALID_QOS_POLICY_ID => QosPolicyId::Invalid,
+            DDS_USERDATA_QOS_POLICY_ID => QosPolicyId::UserData,
+            DDS_DURABILITY_QOS_POLICY_ID => QosPolicyId::Durability,
+            DDS_PRESENTATION_QOS_POLICY_ID => QosPolicyId::Presentation,
+            DDS_DEADLINE_QOS_POLICY_ID => QosPolicyId::Deadline,
+            DDS_LATENCYBUDGET_QOS_POLICY_ID => QosPolicyId::LatencyBudget,
+            DDS_OWNERSHIP_QOS_POLICY_ID => QosPolicyId::Ownership,
+            DDS_OWNERSHIPSTRENGTH_QOS_POLICY_ID => QosPolicyId::OwnershipStrength,
+            DDS_LIVELINESS_QOS_POLICY_ID => QosPolicyId::Liveliness,
+            DDS_TIMEBASEDFILTER_QOS_POLICY_ID => QosPolicyId::TimeBasedFilter,
+            DDS_PARTITION_QOS_POLICY_ID => QosPolicyId::Partition,
+            DDS_RELIABILITY_QOS_POLICY_ID => QosPolicyId::Reliability,
+            DDS_DESTINATIONORDER_QOS_POLICY_ID => QosPolicyId::DestinationOrder,
+            DDS_HISTORY_QOS_POLICY_ID => QosPolicyId::History,
+            DDS_RESOURCELIMITS_QOS_POLICY_ID => QosPolicyId::ResourceLimits,
+            DDS_ENTITYFACTORY_QOS_POLICY_ID => QosPolicyId::EntityFactory,
+            DDS_WRITERDATALIFECYCLE_QOS_POLICY_ID => QosPolicyId::WriterDataLifecycle,
+            DDS_READERDATALIFECYCLE_QOS_POLICY_ID => QosPolicyId::ReaderDataLifecycle,
+            DDS_TOPICDATA_QOS_POLICY_ID => QosPolicyId::TopicData,
+            DDS_GROUPDATA_QOS_POLICY_ID => QosPolicyId::GroupData,
+            DDS_TRANSPORTPRIORITY_QOS_POLICY_ID => QosPolicyId::TransportPriority,
+            DDS_LIFESPAN_QOS_POLICY_ID => QosPolicyId::Lifespan,
+            DDS_DURABILITYSERVICE_QOS_POLICY_ID => QosPolicyId::DurabilityService,
+            DDS_PROPERTY_QOS_POLICY_ID => QosPolicyId::Property,
+            DDS_TYPE_CONSISTENCY_ENFORCEMENT_QOS_POLICY_ID => {
+                QosPolicyId::TypeConsistencyEnforcement
+            }
+            DDS_DATA_REPRESENTATION_QOS_POLICY_ID => QosPolicyId::DataRepresentation,
+            id => QosPolicyId::Unknown(id),
+        }
+    }
+}
+
+/// Status of the writers a reader could not match because of their QoS
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RequestedIncompatibleQosStatus {
+    /// Number of incompatible writers found
+    pub total_count: u32,
+    pub total_count_change: i32,
+    /// The policy that was last found incompatible
+    pub last_policy_id: QosPolicyId,
+}
+
+impl From<dds_requested_incompatible_qos_status_t> for RequestedIncompatibleQosStatus {
+    fn from(status: dds_requested_incompatible_qos_status_t) -> Self {
+        Self {
+            total_count: status.total_count,
+            total_count_change: status.total_count_change,
+            last_policy_id: status.last_policy_id.into(),
+        }
+    }
+}
+
+impl_status!(
+    RequestedIncompatibleQosStatus,
+    DDS_REQUESTED_INCOMPATIBLE_QOS_STATUS_ID,
+    dds_requested_incompatible_qos_status_t,
+    dds_get_requested_incompatible_qos_status,
+    total_count_change
+);
+impl ReaderStatus for RequestedIncompatibleQosStatus {}
+
+/// Status of the readers a writer could not match because of their QoS
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OfferedIncompatibleQosStatus {
+    /// Number of incompatible readers found
+    pub total_count: u32,
+    pub total_count_change: i32,
+    /// The policy that was last found incompatible
+    pub last_policy_id: QosPolicyId,
+}
+
+impl From<dds_offered_incompatible_qos_status_t> for OfferedIncompatibleQosStatus {
+    fn from(status: dds_offered_incompatible_qos_status_t) -> Self {
+        Self {
+            total_count: status.total_count,
+            total_count_change: status.total_count_change,
+            last_policy_id: status.last_policy_id.into(),
+        }
+    }
+}
+
+impl_status!(
+    OfferedIncompatibleQosStatus,
+    DDS_OFFERED_INCOMPATIBLE_QOS_STATUS_ID,
+    dds_offered_incompatible_qos_status_t,
+    dds_get_offered_incompatible_qos_status,
+    total_count_change
+);
+impl WriterStatus for OfferedIncompatibleQosStatus {}
+
+/// Status of the deadlines missed by the instances of a reader
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RequestedDeadlineMissedStatus {
+    /// Number of deadlines missed
+    pub total_count: u32,
+    pub total_count_change: i32,
+    /// The instance that last missed its deadline
+    pub last_instance_handle: InstanceHandle,
+}
+
+impl From<dds_requested_deadline_missed_status_t> for RequestedDeadlineMissedStatus {
+    fn from(status: dds_requested_deadline_missed_status_t) -> Self {
+        Self {
+            total_count: status.total_count,
+            total_count_change: status.total_count_change,
+            last_instance_handle: status.last_instance_handle.into(),
+        }
+    }
+}
+
+impl_status!(
+    RequestedDeadlineMissedStatus,
+    DDS_REQUESTED_DEADLINE_MISSED_STATUS_ID,
+    dds_requested_deadline_missed_status_t,
+    dds_get_requested_deadline_missed_status,
+    total_count_change
+);
+impl ReaderStatus for RequestedDeadlineMissedStatus {}
+
+/// Status of the deadlines missed by the instances of a writer
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OfferedDeadlineMissedStatus {
+    /// Number of deadlines missed
+    pub total_count: u32,
+    pub total_count_change: i32,
+    /// The instance that last missed its deadline
+    pub last_instance_handle: InstanceHandle,
+}
+
+impl From<dds_offered_deadline_missed_status_t> for OfferedDeadlineMissedStatus {
+    fn from(status: dds_offered_deadline_missed_status_t) -> Self {
+        Self {
+            total_count: status.total_count,
+            total_count_change: status.total_count_change,
+            last_instance_handle: status.last_instance_handle.into(),
+        }
+    }
+}
+
+impl_status!(
+    OfferedDeadlineMissedStatus,
+    DDS_OFFERED_DEADLINE_MISSED_STATUS_ID,
+    dds_offered_deadline_missed_status_t,
+    dds_get_offered_deadline_missed_status,
+    total_count_change
+);
+impl WriterStatus for OfferedDeadlineMissedStatus {}
+
+/// Status of the topics found with the same name but another type
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InconsistentTopicStatus {
+    /// Number of inconsistent topics found
+    pub total_count: u32,
+    pub total_count_change: i32,
+}
+
+impl From<dds_inconsistent_topic_status_t> for InconsistentTopicStatus {
+    fn from(status: dds_inconsistent_topic_status_t) -> Self {
+        Self {
+            total_count: status.total_count,
+            total_count_change: status.total_count_change,
+        }
+    }
+}
+
+impl_status!(
+    InconsistentTopicStatus,
+    DDS_INCONSISTENT_TOPIC_STATUS_ID,
+    dds_inconsistent_topic_status_t,
+    dds_get_inconsistent_topic_status,
+    total_count_change
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_qos_policy_id() {
+        assert_eq!(
+            QosPolicyId::from(DDS_INVALID_QOS_POLICY_ID),
+            QosPolicyId::Invalid
+        );
+        assert_eq!(
+            QosPolicyId::from(DDS_DURABILITY_QOS_POLICY_ID),
+            QosPolicyId::Durability
+        );
+        assert_eq!(
+            QosPolicyId::from(DDS_RELIABILITY_QOS_POLICY_ID),
+            QosPolicyId::Reliability
+        );
+        assert_eq!(
+            QosPolicyId::from(DDS_DATA_REPRESENTATION_QOS_POLICY_ID),
+            QosPolicyId::DataRepresentation
+        );
+        // the ids are the positions in dds_qos_policy_id_t
+        assert_eq!(DDS_RELIABILITY_QOS_POLICY_ID, 11);
+        assert_eq!(DDS_DATA_REPRESENTATION_QOS_POLICY_ID, 25);
+        assert_eq!(QosPolicyId::from(26), QosPolicyId::Unknown(26));
+    }
+}
@@ -0,0 +1,292 @@
+/*
+    Copyright 2021 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Status changes of readers and writers as futures and streams.
+//!
+//! The first status stream of a reader or writer without a listener of its own
+//! installs an internal listener that records the last value of each status.
+//! The streams yield the last value whenever the status changed since they last
+//! looked, so changes that happen in quick succession are seen as one. The
+//! counts and the `_change` fields of the status tell what was missed.
+//!
+//! A status handled by a listener no longer triggers the status condition of
+//! the entity and is not passed on to the listeners of its parents. Until a
+//! status stream is created, the statuses of the entity reach both.
+//!
+//! Cyclone resets the change counts of a status whenever it is read or passed
+//! to a listener. The changes are kept here until a typed getter reads the
+//! status with `reset` set.
+
+use std::any::Any;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use crate::dds_listener::DdsListener;
+use crate::dds_status::*;
+use crate::wakers::WakerRegistry;
+use cyclonedds_sys::{dds_set_listener, DDSError, DdsEntity};
+
+/// Number of status ids in Cyclone
+const STATUS_ID_COUNT: usize = 13;
+
+/// The last value of each status of an entity
+pub(crate) struct StatusSlots {
+    /// The number of changes and the last value, indexed by status id
+    slots: Vec<(u64, Option<Box<dyn Any + Send>>)>,
+    /// The changes not yet reset by a getter, indexed by status id
+    kept: Vec<Option<Box<dyn Any + Send>>>,
+    wakers: WakerRegistry,
+    /// True while the listener of the entity records the statuses
+    recording: bool,
+    /// The recording listener, kept until the entity is closed
+    listener: Option<DdsListener>,
+    closed: bool,
+}
+
+/// Status changes recorded by the listener of an entity
+pub(crate) type StatusEvents = Arc<Mutex<StatusSlots>>;
+
+impl StatusSlots {
+    pub(crate) fn new() -> StatusEvents {
+        Arc::new(Mutex::new(Self {
+            slots: (0..STATUS_ID_COUNT).map(|_| (0, None)).collect(),
+            kept: (0..STATUS_ID_COUNT).map(|_| None).collect(),
+            wakers: WakerRegistry::new(),
+            recording: false,
+            listener: None,
+            closed: false,
+        }))
+    }
+
+    /// Slots of an entity with a listener of its own. Its statuses cannot be
+    /// streamed.
+    pub(crate) fn new_closed() -> StatusEvents {
+        let events = Self::new();
+        events.lock().unwrap().closed = true;
+        events
+    }
+
+    /// Record a new value of a status and wake the waiting tasks
+    pub(crate) fn record<S: Status>(&mut self, status: S) {
+        let slot = &mut self.slots[S::ID as usize];
+        slot.0 += 1;
+        slot.1 = Some(Box::new(status));
+        self.keep(status);
+        self.wakers.wake_all();
+    }
+
+    /// Read a status of an entity, including the changes kept since the last
+    /// reset. The changes are kept for the next read unless `reset` is set.
+    pub(crate) fn read<S: Status>(
+        &mut self,
+        entity: &DdsEntity,
+        reset: bool,
+    ) -> Result<S, DDSError> {
+        let status = S::get(entity)?;
+        let status = self.keep(status);
+        if reset {
+            self.kept[S::ID as usize] = None;
+        }
+        Ok(status)
+    }
+
+    // add the changes of a status to the kept changes
+    fn keep<S: Status>(&mut self, mut status: S) -> S {
+        let kept = &mut self.kept[S::ID as usize];
+        if let Some(earlier) = kept.as_ref().and_then(|s| s.downcast_ref::<S>()) {
+            status.add_changes(earlier);
+        }
+        *kept = Some(Box::new(status));
+        status
+    }
+
+    /// Mark the entity as deleted, or as no longer recording because it got
+    /// another listener. The streams end.
+    pub(crate) fn close(&mut self) {
+        self.recording = false;
+        self.closed = true;
+        // the listener refers back to the slots
+        self.listener = None;
+        self.wakers.wake_all();
+    }
+
+    fn changes<S: Status>(&self) -> u64 {
+        self.slots[S::ID as usize].0
+    }
+
+    fn last<S: Status>(&self) -> Option<S> {
+        self.slots[S::ID as usize]
+            .1
+            .as_ref()
+            .and_then(|status| status.downcast_ref::<S>())
+            .copied()
+    }
+}
+
+/// Start recording the statuses of an entity, unless it already does. The
+/// recording callbacks are added to the listener made by `base`, which replaces
+/// the listener of the entity. The callbacks of the replaced listener must not
+/// lock the slots. Fails with `PreconditionNotMet` if the entity has a listener
+/// of its own.
+pub(crate) fn start_recording<F>(
+    events: &StatusEvents,
+    entity: &DdsEntity,
+    base: F,
+    record: fn(DdsListener, &StatusEvents) -> DdsListener,
+) -> Result<(), DDSError>
+where
+    F: FnOnce() -> DdsListener,
+{
+    let mut slots = events.lock().unwrap();
+    if slots.closed {
+        return Err(DDSError::PreconditionNotMet);
+    } else if slots.recording {
+        return Ok(());
+    }
+
+    let listener = record(base(), events).hook();
+    // Cyclone waits for the callbacks of the replaced listener to return
+    let ret = unsafe { dds_set_listener(entity.entity(), (&listener).into()) };
+    if ret == 0 {
+        slots.recording = true;
+        slots.listener = Some(listener);
+        Ok(())
+    } else {
+        Err(DDSError::from(ret))
+    }
+}
+
+/// Add callbacks recording the reader statuses to a listener
+pub(crate) fn record_reader_statuses(listener: DdsListener, events: &StatusEvents) -> DdsListener {
+    let (matched, liveliness, lost, rejected, incompatible, deadline) = (
+        events.clone(),
+        events.clone(),
+        events.clone(),
+        events.clone(),
+        events.clone(),
+        events.clone(),
+    );
+    listener
+        .on_subscription_matched(move |_, status| matched.lock().unwrap().record(status))
+        .on_liveliness_changed(move |_, status| liveliness.lock().unwrap().record(status))
+        .on_sample_lost(move |_, status| lost.lock().unwrap().record(status))
+        .on_sample_rejected(move |_, status| rejected.lock().unwrap().record(status))
+        .on_requested_incompatible_qos(move |_, status| incompatible.lock().unwrap().record(status))
+        .on_requested_deadline_missed(move |_, status| deadline.lock().unwrap().record(status))
+}
+
+/// Add callbacks recording the writer statuses to a listener
+pub(crate) fn record_writer_statuses(listener: DdsListener, events: &StatusEvents) -> DdsListener {
+    let (matched, liveliness, incompatible, deadline) = (
+        events.clone(),
+        events.clone(),
+        events.clone(),
+        events.clone(),
+    );
+    listener
+        .on_publication_matched(move |_, status| matched.lock().unwrap().record(status))
+        .on_liveliness_lost(move |_, status| liveliness.lock().unwrap().record(status))
+        .on_offered_incompatible_qos(move |_, status| incompatible.lock().unwrap().record(status))
+        .on_offered_deadline_missed(move |_, status| deadline.lock().unwrap().record(status))
+}
+
+/// Stream of the changes of one status of a reader or writer. Created by
+/// `DdsReader::status_stream` or `DdsWriter::status_stream`. Only the changes
+/// after the stream was created are yielded. The stream ends when the entity
+/// is deleted.
+pub struct StatusStream<S: Status> {
+    events: StatusEvents,
+    seen: u64,
+    phantom: PhantomData<S>,
+}
+
+impl<S: Status> StatusStream<S> {
+    pub(crate) fn new(events: StatusEvents) -> Self {
+        let seen = events.lock().unwrap().changes::<S>();
+        Self {
+            events,
+            seen,
+            phantom: PhantomData,
+        }
+    }
+}
+
+// The stream is never pinned structurally
+impl<S: Status> Unpin for StatusStream<S> {}
+
+impl<S: Status> Stream for StatusStream<S> {
+    type Item = S;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let events = self.events.clone();
+        let mut events = events.lock().unwrap();
+        let changes = events.changes::<S>();
+        if changes > self.seen {
+            self.seen = changes;
+            Poll::Ready(events.last::<S>())
+        } else if events.closed {
+            Poll::Ready(None)
+        } else {
+            events.wakers.register(ctx.waker());
+            Poll::Pending
+        }
+    }
+}
+
+/// Wait for the next item of a status stream. Fails with `AlreadyDeleted` if
+/// the entity is deleted first.
+pub(crate) async fn next_change<S: Status>(mut stream: StatusStream<S>) -> Result<S, DDSError> {
+    std::future::poll_fn(|ctx| Pin::new(&mut stream).poll_next(ctx))
+        .await
+        .ok_or(DDSError::AlreadyDeleted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::StreamExt;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_status_stream_coalesces_changes() {
+        let events = StatusSlots::new();
+        events.lock().unwrap().record(SampleLostStatus {
+            total_count: 1,
+            total_count_change: 1,
+        });
+        // changes before the stream was created are not seen
+        let mut stream = StatusStream::<SampleLostStatus>::new(events.clone());
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            for total_count in 2..=4 {
+                events.lock().unwrap().record(SampleLostStatus {
+                    total_count,
+                    total_count_change: 1,
+                });
+            }
+            let status = stream.next().await.unwrap();
+            assert_eq!(status.total_count, 4);
+
+            events.lock().unwrap().close();
+            assert!(stream.next().await.is_none());
+        });
+    }
+}
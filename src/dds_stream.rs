@@ -0,0 +1,332 @@
+/*
+    Copyright 2021 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+//! Streams of the samples of an async reader.
+//!
+//! The streams take the samples from the reader in batches and yield them one
+//! at a time. The samples are moved out of the reader and are only cloned when
+//! another reader of the same data still holds them; `SharedSampleStream`
+//! yields them shared instead. The streams do not borrow the reader and end
+//! when the reader is deleted. A failure to take the samples of the reader is
+//! returned as an `Err` item.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use cyclonedds_sys::{DDSError, DdsEntity};
+use futures_core::Stream;
+
+use crate::dds_reader::{DdsReader, ReaderWaker};
+use crate::error::ReaderError;
+use crate::serdes::{SampleBuffer, SampleInfo, TopicType};
+
+type Batched<S> = Result<(Option<S>, SampleInfo), ReaderError>;
+
+// Moves the samples taken into the buffer out of it
+type TakeBatch<T, S> = fn(&mut SampleBuffer<T>) -> Vec<(Option<S>, SampleInfo)>;
+
+// Takes batches of samples from the reader and queues them
+struct SampleBatches<T: TopicType, S> {
+    entity: DdsEntity,
+    waker: ReaderWaker,
+    buffer: SampleBuffer<T>,
+    take_batch: TakeBatch<T, S>,
+    pending: VecDeque<(Option<S>, SampleInfo)>,
+    ended: bool,
+}
+
+impl<T, S> SampleBatches<T, S>
+where
+    T: TopicType,
+{
+    fn new(
+        entity: DdsEntity,
+        waker: ReaderWaker,
+        batch_size: usize,
+        take_batch: TakeBatch<T, S>,
+    ) -> Self {
+        Self {
+            entity,
+            waker,
+            buffer: SampleBuffer::new(batch_size.max(1)),
+            take_batch,
+            pending: VecDeque::with_capacity(batch_size),
+            ended: false,
+        }
+    }
+
+    fn poll_next(&mut self, ctx: &mut Context<'_>) -> Poll<Option<Batched<S>>> {
+        if let Some(sample) = self.pending.pop_front() {
+            return Poll::Ready(Some(Ok(sample)));
+        }
+        if self.ended {
+            return Poll::Ready(None);
+        }
+
+        // Lock the waker first so a data available callback cannot be missed
+        let mut waker = self.waker.lock().unwrap();
+        if waker.is_closed() {
+            self.ended = true;
+            return Poll::Ready(None);
+        }
+        match DdsReader::<T>::readn_from_entity_now(&self.entity, &mut self.buffer, true) {
+            Ok(result) if result.is_empty() => {
+                waker.register(ctx.waker());
+                Poll::Pending
+            }
+            Ok(_) => {
+                self.pending.extend((self.take_batch)(&mut self.buffer));
+                Poll::Ready(self.pending.pop_front().map(Ok))
+            }
+            Err(DDSError::AlreadyDeleted) => {
+                self.ended = true;
+                Poll::Ready(None)
+            }
+            Err(e) => Poll::Ready(Some(Err(ReaderError::DdsError(e)))),
+        }
+    }
+
+    // skip the invalid samples, which carry no data
+    fn poll_next_valid(&mut self, ctx: &mut Context<'_>) -> Poll<Option<Result<S, ReaderError>>> {
+        loop {
+            match self.poll_next(ctx) {
+                Poll::Ready(Some(Ok((Some(sample), _)))) => return Poll::Ready(Some(Ok(sample))),
+                Poll::Ready(Some(Ok((None, _)))) => continue,
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Stream of the samples of an async reader. Created by `DdsReader::stream`.
+/// Invalid samples, which carry no data, are skipped.
+pub struct SampleStream<T: TopicType> {
+    batches: SampleBatches<T, T>,
+}
+
+impl<T> SampleStream<T>
+where
+    T: TopicType + Clone,
+{
+    pub(crate) fn new(entity: DdsEntity, waker: ReaderWaker, batch_size: usize) -> Self {
+        Self {
+            batches: SampleBatches::new(
+                entity,
+                waker,
+                batch_size,
+                SampleBuffer::take_owned_with_info,
+            ),
+        }
+    }
+}
+
+// The streams are never pinned structurally
+impl<T: TopicType> Unpin for SampleStream<T> {}
+
+impl<T> Stream for SampleStream<T>
+where
+    T: TopicType,
+{
+    type Item = Result<T, ReaderError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.batches.poll_next_valid(ctx)
+    }
+}
+
+/// Stream of the samples of an async reader, shared with any other reader of
+/// the same data instead of cloned. Created by `DdsReader::stream_shared`.
+/// Invalid samples, which carry no data, are skipped.
+pub struct SharedSampleStream<T: TopicType> {
+    batches: SampleBatches<T, Arc<T>>,
+}
+
+impl<T> SharedSampleStream<T>
+where
+    T: TopicType,
+{
+    pub(crate) fn new(entity: DdsEntity, waker: ReaderWaker, batch_size: usize) -> Self {
+        Self {
+            batches: SampleBatches::new(
+                entity,
+                waker,
+                batch_size,
+                SampleBuffer::take_shared_with_info,
+            ),
+        }
+    }
+}
+
+impl<T: TopicType> Unpin for SharedSampleStream<T> {}
+
+impl<T> Stream for SharedSampleStream<T>
+where
+    T: TopicType,
+{
+    type Item = Result<Arc<T>, ReaderError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.batches.poll_next_valid(ctx)
+    }
+}
+
+/// Stream of the samples of an async reader together with their sample info.
+/// Created by `DdsReader::stream_with_info`. Invalid samples are yielded
+/// without data.
+pub struct SampleInfoStream<T: TopicType> {
+    batches: SampleBatches<T, T>,
+}
+
+impl<T> SampleInfoStream<T>
+where
+    T: TopicType + Clone,
+{
+    pub(crate) fn new(entity: DdsEntity, waker: ReaderWaker, batch_size: usize) -> Self {
+        Self {
+            batches: SampleBatches::new(
+                entity,
+                waker,
+                batch_size,
+                SampleBuffer::take_owned_with_info,
+            ),
+        }
+    }
+}
+
+impl<T: TopicType> Unpin for SampleInfoStream<T> {}
+
+impl<T> Stream for SampleInfoStream<T>
+where
+    T: TopicType,
+{
+    type Item = Result<(Option<T>, SampleInfo), ReaderError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.batches.poll_next(ctx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        DDSError, DdsListener, DdsParticipant, DdsPublisher, DdsQos, DdsSubscriber, DdsTopic,
+        DdsWriter,
+    };
+    use cdds_derive::Topic;
+    use cyclonedds_sys::dds_history_kind;
+    use futures::{future, StreamExt};
+    use serde_derive::{Deserialize, Serialize};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+
+    #[derive(Serialize, Deserialize, Topic, Debug, PartialEq, Clone, Default)]
+    struct Counter {
+        value: u32,
+    }
+
+    fn keep_last(depth: i32) -> DdsQos {
+        let mut qos = DdsQos::create().unwrap();
+        qos.set_history(dds_history_kind::DDS_HISTORY_KEEP_LAST, depth);
+        qos
+    }
+
+    #[test]
+    fn test_sample_stream() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic = Counter::create_topic(&participant, Some("sample_stream"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = DdsReader::create_async(&subscriber, topic, Some(keep_last(10))).unwrap();
+
+        for value in 1..=5 {
+            writer.write(Arc::new(Counter { value })).unwrap();
+        }
+
+        let rt = Runtime::new().unwrap();
+        let even: Vec<_> = rt.block_on(
+            reader
+                .stream(2)
+                .unwrap()
+                .map(Result::unwrap)
+                .filter(|sample| future::ready(sample.value % 2 == 0))
+                .take(2)
+                .map(|sample| sample.value)
+                .collect(),
+        );
+        assert_eq!(even, vec![2, 4]);
+    }
+
+    #[test]
+    fn test_shared_sample_stream() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Counter::create_topic(&participant, Some("shared_sample_stream"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = DdsReader::create_async(&subscriber, topic.clone(), None).unwrap();
+        let other_reader = DdsReader::create_async(&subscriber, topic, None).unwrap();
+
+        writer.write(Arc::new(Counter { value: 3 })).unwrap();
+
+        let rt = Runtime::new().unwrap();
+        let (sample, other_sample) = rt.block_on(async {
+            let sample = reader.stream_shared(1).unwrap().next().await;
+            let other_sample = other_reader.stream_shared(1).unwrap().next().await;
+            (sample.unwrap().unwrap(), other_sample.unwrap().unwrap())
+        });
+        assert_eq!(*sample, Counter { value: 3 });
+        // both readers hold the sample that was written, not a copy
+        assert!(Arc::ptr_eq(&sample, &other_sample));
+    }
+
+    #[test]
+    fn test_stream_ends_when_reader_deleted() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic =
+            Counter::create_topic(&participant, Some("sample_info_stream"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let mut writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let reader = DdsReader::create_async(&subscriber, topic, None).unwrap();
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut stream = reader.stream_with_info(4).unwrap();
+            writer.write(Arc::new(Counter { value: 7 })).unwrap();
+
+            let (sample, info) = stream.next().await.unwrap().unwrap();
+            assert_eq!(sample, Some(Counter { value: 7 }));
+            assert!(info.valid_data());
+
+            let waiting = tokio::spawn(async move { stream.next().await.is_none() });
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            drop(reader);
+            let ended = tokio::time::timeout(Duration::from_secs(1), waiting)
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(ended);
+        });
+    }
+}
@@ -14,7 +14,9 @@
     limitations under the License.
 */
 
+use crate::dds_api::{CoherentSet, StateFilter};
 use crate::{DdsListener, DdsParticipant, DdsQos, DdsReadable};
+use cyclonedds_sys::*;
 pub use cyclonedds_sys::{DDSError, DdsDomainId, DdsEntity};
 use std::convert::From;
 
@@ -70,6 +72,103 @@ impl<'a> DdsSubscriber {
             }
         }
     }
+
+    /// Begin coherent access to the readers of this subscriber. This does not
+    /// hold back any samples: with `coherent_access` in the presentation Qos,
+    /// the readers only get the samples of a coherent set published with
+    /// `DdsPublisher::begin_coherent` once the whole set has been received,
+    /// whether or not an access is active. The access marks the reads that
+    /// belong together and ends when the returned guard is committed or dropped.
+    pub fn begin_coherent(&self) -> Result<CoherentSet<'_>, DDSError> {
+        CoherentSet::begin(&self.0)
+    }
+
+    /// Begin access to the readers of a subscriber with a `presentation` of
+    /// GROUP scope and coherent or ordered access. The samples of the readers
+    /// returned by `get_datareaders` are read or taken while the access is
+    /// active. The access ends with `end_access`, or when the guard is dropped.
+    /// Cyclone before 0.9 fails with `DDSError::Unsupported`.
+    pub fn begin_access(&self) -> Result<GroupAccess<'_>, DDSError> {
+        CoherentSet::begin(&self.0).map(GroupAccess)
+    }
+
+    /// End an access started by `begin_access`
+    pub fn end_access(&self, access: GroupAccess<'_>) -> Result<(), DDSError> {
+        access.0.commit()
+    }
+
+    /// Get the readers of this subscriber that have samples matching the filter.
+    /// Compare the entities with `Entity::entity` of the readers. Each reader is
+    /// listed once, Cyclone does not list a reader once per sample as the DDS
+    /// specification describes for ordered access.
+    ///
+    /// Cyclone cannot check the sample states of a reader without a condition,
+    /// so each call creates and deletes a read condition on every reader of the
+    /// subscriber.
+    pub fn get_datareaders(&self, filter: StateFilter) -> Result<Vec<DdsEntity>, DDSError> {
+        let mut readers = Vec::new();
+        for reader in self.children()? {
+            if has_samples(reader, filter)? {
+                readers.push(unsafe { DdsEntity::new(reader) });
+            }
+        }
+        Ok(readers)
+    }
+
+    /// Call the `on_data_available` listeners of the readers of this subscriber
+    /// that have data.
+    pub fn notify_datareaders(&self) -> Result<(), DDSError> {
+        let ret = unsafe { dds_notify_readers(self.0.entity()) };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(DDSError::from(ret))
+        }
+    }
+
+    // the readers of the subscriber
+    fn children(&self) -> Result<Vec<dds_entity_t>, DDSError> {
+        unsafe {
+            let n = dds_get_children(self.0.entity(), std::ptr::null_mut(), 0);
+            if n < 0 {
+                return Err(DDSError::from(n));
+            }
+            let mut children = vec![0; n as usize];
+            let n = dds_get_children(
+                self.0.entity(),
+                children.as_mut_ptr(),
+                children.len() as size_t,
+            );
+            if n < 0 {
+                return Err(DDSError::from(n));
+            }
+            // readers may have been deleted in between
+            children.truncate(n as usize);
+            Ok(children)
+        }
+    }
+}
+
+/// Access to the readers of a subscriber, started by `DdsSubscriber::begin_access`.
+/// The access is ended by `DdsSubscriber::end_access`, or when the guard is
+/// dropped.
+pub struct GroupAccess<'a>(CoherentSet<'a>);
+
+// check with a temporary read condition if a reader has samples matching the filter
+fn has_samples(reader: dds_entity_t, filter: StateFilter) -> Result<bool, DDSError> {
+    unsafe {
+        let condition = dds_create_readcondition(reader, filter.mask());
+        if condition < 0 {
+            return Err(DDSError::from(condition));
+        }
+        let triggered = dds_triggered(condition);
+        dds_delete(condition);
+        if triggered < 0 {
+            Err(DDSError::from(triggered))
+        } else {
+            Ok(triggered > 0)
+        }
+    }
 }
 
 impl<'a> DdsReadable for DdsSubscriber {
@@ -77,3 +176,121 @@ impl<'a> DdsReadable for DdsSubscriber {
         &self.0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DdsPublisher, DdsReader, DdsTopic, DdsWriter, Entity, SampleBuffer, TopicType};
+    use cdds_derive::Topic;
+    use serde_derive::{Deserialize, Serialize};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+
+    #[derive(Serialize, Deserialize, Topic, Debug, PartialEq, Default)]
+    struct OrderPlaced {
+        #[topic_key]
+        order: u32,
+    }
+
+    #[derive(Serialize, Deserialize, Topic, Debug, PartialEq, Default)]
+    struct OrderShipped {
+        #[topic_key]
+        order: u32,
+    }
+
+    fn group_qos() -> DdsQos {
+        let mut qos = DdsQos::create().unwrap();
+        qos.set_presentation(
+            dds_presentation_access_scope_kind::DDS_PRESENTATION_GROUP,
+            true,
+            true,
+        );
+        qos
+    }
+
+    #[test]
+    fn test_group_access() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let placed = OrderPlaced::create_topic(&participant, None, None, None).unwrap();
+        let shipped = OrderShipped::create_topic(&participant, None, None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, Some(group_qos()), None).unwrap();
+        let subscriber = DdsSubscriber::create(&participant, Some(group_qos()), None).unwrap();
+        let mut placed_writer = DdsWriter::create(&publisher, placed.clone(), None, None).unwrap();
+        let mut shipped_writer =
+            DdsWriter::create(&publisher, shipped.clone(), None, None).unwrap();
+        let placed_reader = DdsReader::create(&subscriber, placed, None, None).unwrap();
+        let shipped_reader = DdsReader::create(&subscriber, shipped, None, None).unwrap();
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(placed_writer.wait_for_readers(1, Duration::from_secs(5)))
+            .unwrap();
+        rt.block_on(shipped_writer.wait_for_readers(1, Duration::from_secs(5)))
+            .unwrap();
+        assert!(subscriber
+            .get_datareaders(StateFilter::not_read())
+            .unwrap()
+            .is_empty());
+
+        // Cyclone before 0.9 has no coherent sets, the samples are then
+        // delivered as they are written
+        let maybe_set = match publisher.begin_coherent() {
+            Ok(set) => Some(set),
+            Err(e) => {
+                assert_eq!(e, DDSError::Unsupported);
+                None
+            }
+        };
+        placed_writer
+            .write(Arc::new(OrderPlaced { order: 1 }))
+            .unwrap();
+        shipped_writer
+            .write(Arc::new(OrderShipped { order: 1 }))
+            .unwrap();
+        if let Some(set) = maybe_set {
+            set.commit().unwrap();
+        }
+        let timeout = Duration::from_secs(1);
+        assert!(placed_reader.wait_for_unread(&participant, timeout));
+        assert!(shipped_reader.wait_for_unread(&participant, timeout));
+
+        let maybe_access = match subscriber.begin_access() {
+            Ok(access) => Some(access),
+            Err(e) => {
+                assert_eq!(e, DDSError::Unsupported);
+                None
+            }
+        };
+        let readers = subscriber.get_datareaders(StateFilter::not_read()).unwrap();
+        assert_eq!(readers.len(), 2);
+        assert!(readers.contains(placed_reader.entity()));
+        assert!(readers.contains(shipped_reader.entity()));
+
+        let mut placed_samples = OrderPlaced::create_sample_buffer(1);
+        let mut shipped_samples = OrderShipped::create_sample_buffer(1);
+        assert_eq!(
+            placed_reader.read_now(&mut placed_samples).unwrap().valid,
+            1
+        );
+        assert_eq!(
+            shipped_reader.read_now(&mut shipped_samples).unwrap().valid,
+            1
+        );
+        if let Some(access) = maybe_access {
+            subscriber.end_access(access).unwrap();
+        }
+
+        // the samples were read, but are still in the readers
+        assert!(subscriber
+            .get_datareaders(StateFilter::not_read())
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            subscriber
+                .get_datareaders(StateFilter::read())
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
@@ -18,11 +18,21 @@ use cyclonedds_sys::*;
 use std::convert::From;
 use std::ffi::c_void;
 use std::ptr::NonNull;
+use std::time::{Duration, Instant};
 
 pub use cyclonedds_sys::DdsEntity;
 use std::marker::PhantomData;
 
-use crate::serdes::{Sample, TopicType};
+use crate::common::{BlockingFuture, Cancel};
+use crate::dds_api::{self, MatchedEndpoint};
+use crate::dds_listener_traits::WriterListener;
+use crate::dds_status::{
+    LivelinessLostStatus, OfferedDeadlineMissedStatus, OfferedIncompatibleQosStatus,
+    PublicationMatchedStatus, WriterStatus,
+};
+use crate::dds_status_events::{self, StatusEvents, StatusSlots, StatusStream};
+use crate::error::WriterError;
+use crate::serdes::{self, Sample, TopicType};
 use crate::SampleBuffer;
 use crate::{dds_listener::DdsListener, dds_qos::DdsQos, dds_topic::DdsTopic, DdsWritable, Entity};
 
@@ -54,6 +64,12 @@ where
         self
     }
 
+    /// Create a writer with a listener implementing `WriterListener`
+    pub fn with_listener_impl<L: WriterListener>(mut self, listener: L) -> Self {
+        self.maybe_listener = Some(DdsListener::from_writer_listener(listener));
+        self
+    }
+
     pub fn create(
         self,
         entity: &dyn DdsWritable,
@@ -120,18 +136,31 @@ where
 }
 
 #[derive(Clone)]
-pub struct DdsWriter<T: Sized + TopicType>(DdsEntity, Option<DdsListener>, PhantomData<T>);
+pub struct DdsWriter<T: Sized + TopicType>(
+    DdsEntity,
+    Option<DdsListener>,
+    StatusEvents,
+    PhantomData<T>,
+);
 
 impl<'a, T> DdsWriter<T>
 where
     T: Sized + TopicType,
 {
+    /// Create a writer. The status changes of a writer created without a
+    /// listener can be streamed with `status_stream` and `status_changed`.
     pub fn create(
         entity: &dyn DdsWritable,
         topic: DdsTopic<T>,
         maybe_qos: Option<DdsQos>,
         maybe_listener: Option<DdsListener>,
     ) -> Result<Self, DDSError> {
+        let status_events = if maybe_listener.is_some() {
+            StatusSlots::new_closed()
+        } else {
+            StatusSlots::new()
+        };
+
         unsafe {
             let w = dds_create_writer(
                 entity.entity().entity(),
@@ -143,36 +172,51 @@ where
             );
 
             if w >= 0 {
-                Ok(DdsWriter(DdsEntity::new(w), maybe_listener, PhantomData))
+                Ok(DdsWriter(
+                    DdsEntity::new(w),
+                    maybe_listener,
+                    status_events,
+                    PhantomData,
+                ))
             } else {
                 Err(DDSError::from(w))
             }
         }
     }
 
-    pub fn write_to_entity(entity: &DdsEntity, msg: std::sync::Arc<T>) -> Result<(), DDSError> {
+    pub fn write_to_entity(entity: &DdsEntity, msg: std::sync::Arc<T>) -> Result<(), WriterError> {
         unsafe {
             let sample = Sample::<T>::from(msg);
             let sample = &sample as *const Sample<T>;
             let sample = sample as *const ::std::os::raw::c_void;
+            serdes::take_serialization_error();
+            let start = Instant::now();
             let ret = dds_write(entity.entity(), sample);
             if ret >= 0 {
                 Ok(())
             } else {
-                Err(DDSError::from(ret))
+                Err(write_error(ret, start.elapsed()))
             }
         }
     }
 
-    pub fn write(&mut self, msg: std::sync::Arc<T>) -> Result<(), DDSError> {
+    pub fn write(&mut self, msg: std::sync::Arc<T>) -> Result<(), WriterError> {
         Self::write_to_entity(&self.0, msg)
     }
 
+    /// Send out any samples that Cyclone has batched for this writer
+    /// without waiting for the batch to fill up. Cyclone does not implement
+    /// `dds_suspend` and `dds_resume`, so publication cannot be suspended;
+    /// batching with an explicit flush is the closest it offers.
+    pub fn write_flush(&self) {
+        unsafe { dds_write_flush(self.0.entity()) }
+    }
+
     // Loan memory buffers for zero copy operation. Only supported for fixed size types
-    pub fn loan(&mut self) -> Result<Loaned<T>, DDSError> {
+    pub fn loan(&mut self) -> Result<Loaned<T>, WriterError> {
         if !T::is_fixed_size() {
             // Loaning is not supported for types that are not fixed size
-            return Err(DDSError::Unsupported);
+            return Err(WriterError::LoanUnsupported);
         }
 
         let mut p_sample: *mut T = std::ptr::null_mut();
@@ -187,12 +231,17 @@ where
                 ),
             })
         } else {
-            Err(DDSError::from(res))
+            // without shared memory, Cyclone has no loans to hand out
+            match DDSError::from(res) {
+                DDSError::Unsupported => Err(WriterError::LoanUnsupported),
+                e => Err(e.into()),
+            }
         }
     }
 
     // Return the loaned buffer.  If the buffer was initialized, then write the data to be published
-    pub fn return_loan(&mut self, mut buffer: Loaned<T>) -> Result<(), DDSError> {
+    pub fn return_loan(&mut self, mut buffer: Loaned<T>) -> Result<(), WriterError> {
+        let start = Instant::now();
         let res = match &mut buffer.inner {
             LoanedInner::Uninitialized(p, entity) => {
                 let mut p_sample = p.as_ptr();
@@ -210,16 +259,99 @@ where
         if res == 0 {
             Ok(())
         } else {
-            Err(DDSError::from(res))
+            Err(write_error(res, start.elapsed()))
         }
     }
 
+    /// Get a status of this writer. The `_change` counts are the changes since
+    /// the status was last read with `reset` set, unless a listener given by the
+    /// application handles the status.
+    pub fn status<S: WriterStatus>(&self, reset: bool) -> Result<S, DDSError> {
+        self.2.lock().unwrap().read(&self.0, reset)
+    }
+
+    /// Get the status of the readers matched with this writer. The change counts are reset.
+    pub fn publication_matched_status(&self) -> Result<PublicationMatchedStatus, DDSError> {
+        self.status(true)
+    }
+
+    /// Get the status of the liveliness lost by this writer
+    pub fn liveliness_lost_status(&self, reset: bool) -> Result<LivelinessLostStatus, DDSError> {
+        self.status(reset)
+    }
+
+    /// Get the status of the deadlines missed by the instances of this writer
+    pub fn offered_deadline_missed_status(
+        &self,
+        reset: bool,
+    ) -> Result<OfferedDeadlineMissedStatus, DDSError> {
+        self.status(reset)
+    }
+
+    /// Get the status of the readers this writer could not match because of their QoS
+    pub fn offered_incompatible_qos_status(
+        &self,
+        reset: bool,
+    ) -> Result<OfferedIncompatibleQosStatus, DDSError> {
+        self.status(reset)
+    }
+
+    /// Get the remote readers currently matched with this writer
+    pub fn matched_subscriptions(&self) -> Result<Vec<MatchedEndpoint>, DDSError> {
+        dds_api::matched_endpoints(
+            &self.0,
+            dds_get_matched_subscriptions,
+            dds_get_matched_subscription_data,
+        )
+    }
+
+    /// Wait until at least `n` readers are matched with this writer. Fails with
+    /// `DDSError::Timeout` if this does not happen within `timeout`.
+    pub async fn wait_for_readers(
+        &self,
+        n: u32,
+        timeout: Duration,
+    ) -> Result<PublicationMatchedStatus, DDSError> {
+        let entity = self.0.clone();
+        let events = self.2.clone();
+        BlockingFuture::new(move |cancel| {
+            wait_for_matched_readers(&entity, &events, n, timeout, cancel)
+        })
+        .await
+    }
+
+    /// Stream of the changes of a status of this writer, such as
+    /// `PublicationMatchedStatus` or `OfferedDeadlineMissedStatus`. Fails with
+    /// `PreconditionNotMet` if the writer has a listener of its own.
+    ///
+    /// The first stream installs a listener recording the statuses of the
+    /// writer. From then on these statuses no longer trigger the status
+    /// condition of the writer and are not passed on to the listeners of the
+    /// publisher and participant.
+    pub fn status_stream<S: WriterStatus>(&self) -> Result<StatusStream<S>, DDSError> {
+        dds_status_events::start_recording(
+            &self.2,
+            &self.0,
+            DdsListener::new,
+            dds_status_events::record_writer_statuses,
+        )?;
+        Ok(StatusStream::new(self.2.clone()))
+    }
+
+    /// Wait for the next change of a status of this writer. See `status_stream`.
+    pub async fn status_changed<S: WriterStatus>(&self) -> Result<S, DDSError> {
+        dds_status_events::next_change(self.status_stream()?).await
+    }
+
+    /// Replace the listener of this writer. The status changes are no longer
+    /// recorded and the status streams end.
     pub fn set_listener(&mut self, listener: DdsListener) -> Result<(), DDSError> {
         unsafe {
             let refl = &listener;
             let rc = dds_set_listener(self.0.entity(), refl.into());
             if rc == 0 {
                 self.1 = Some(listener);
+                self.2.lock().unwrap().close();
                 Ok(())
             } else {
                 Err(DDSError::from(rc))
@@ -248,9 +380,94 @@ where
                 //panic!("cannot delete Writer: {}", ret);
             }
         }
+        self.2.lock().unwrap().close();
     }
 }
 
+// Map the return code of a write to a WriterError. Serialization failures
+// surface from Cyclone as a bad parameter, the reason is recorded by serdes.
+fn write_error(ret: dds_return_t, blocked_for: Duration) -> WriterError {
+    match DDSError::from(ret) {
+        DDSError::Timeout => WriterError::Timeout {
+            blocked_for: Some(blocked_for),
+            source: DDSError::Timeout,
+        },
+        DDSError::BadParameter => match serdes::take_serialization_error() {
+            Some(reason) => WriterError::SerializationFailed(reason),
+            None => DDSError::BadParameter.into(),
+        },
+        e => e.into(),
+    }
+}
+
+// Blocking wait for matched readers. The status condition of the writer does not
+// trigger when a listener handles the publication matched status, so the status
+// is also checked every MATCH_POLL_INTERVAL. The wait ends early when the future
+// is dropped.
+fn wait_for_matched_readers(
+    entity: &DdsEntity,
+    events: &StatusEvents,
+    n: u32,
+    timeout: Duration,
+    cancel: &Cancel,
+) -> Result<PublicationMatchedStatus, DDSError> {
+    let deadline = Instant::now() + timeout;
+    let participant = unsafe { dds_get_participant(entity.entity()) };
+    let waitset = unsafe { dds_create_waitset(participant) };
+    if waitset < 0 {
+        return Err(DDSError::from(waitset));
+    }
+
+    let result = match unsafe { dds_waitset_attach(waitset, entity.entity(), 0) } {
+        0 => match cancel.attach(waitset, participant) {
+            Ok(_guard) => loop {
+                if cancel.is_cancelled() {
+                    break Err(DDSError::Timeout);
+                }
+                // keep the changes for the getters
+                let status = match events.lock().unwrap().read(entity, false) {
+                    Ok(status) => status,
+                    Err(e) => break Err(e),
+                };
+                let status: PublicationMatchedStatus = status;
+                if status.current_count >= n {
+                    break Ok(status);
+                }
+
+                let now = Instant::now();
+                if now >= deadline {
+                    break Err(DDSError::Timeout);
+                }
+                let wait = std::cmp::min(deadline - now, MATCH_POLL_INTERVAL);
+                let ret = unsafe {
+                    dds_waitset_wait(waitset, std::ptr::null_mut(), 0, wait.as_nanos() as i64)
+                };
+                if ret < 0 {
+                    break Err(DDSError::from(ret));
+                } else if ret > 0 {
+                    // Triggered by a status we do not consume. Sleep instead of spinning.
+                    match dds_api::dds_get_status_changes(entity) {
+                        Ok(changes)
+                            if !changes.is_set(dds_api::DDS_PUBLICATION_MATCHED_STATUS_ID) =>
+                        {
+                            cancel.sleep(wait);
+                        }
+                        _ => {}
+                    }
+                }
+            },
+            Err(e) => Err(e),
+        },
+        err => Err(DDSError::from(err)),
+    };
+
+    unsafe { dds_delete(waitset) };
+    result
+}
+
+/// Upper bound for a single wait on the writer status condition
+const MATCH_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 #[cfg(test)]
 mod test {
     use core::panic;
@@ -258,7 +475,7 @@ mod test {
 
     use super::*;
     use crate::{DdsParticipant, DdsReader, DdsSubscriber};
-    use crate::{DdsPublisher, DdsWriter};
+    use crate::{DdsPublisher, DdsWriter, TopicFixedSize};
 
     use cdds_derive::{Topic, TopicFixedSize};
     use serde_derive::{Deserialize, Serialize};
@@ -337,6 +554,108 @@ mod test {
         }
     }
 
+    // A field whose Serialize implementation always fails
+    #[derive(Deserialize, Debug, Default)]
+    struct Unserializable;
+
+    impl serde::Serialize for Unserializable {
+        fn serialize<S: serde::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("refusing to serialize"))
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Topic, Debug, Default)]
+    struct BrokenTopic {
+        pub value: u32,
+        pub broken: Unserializable,
+    }
+
+    #[test]
+    fn test_writer_errors() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic = BrokenTopic::create_topic(&participant, None, None, None).unwrap();
+        let mut writer = DdsWriter::create(&participant, topic, None, None).unwrap();
+
+        match writer.write(Arc::new(BrokenTopic::default())) {
+            Err(WriterError::SerializationFailed(reason)) => {
+                assert!(reason.contains("refusing to serialize"))
+            }
+            other => panic!("Unexpected write result {:?}", other),
+        }
+
+        let topic = AnotherTopic::create_topic(&participant, None, None, None).unwrap();
+        let mut writer = DdsWriter::create(&participant, topic, None, None).unwrap();
+        assert!(matches!(writer.loan(), Err(WriterError::LoanUnsupported)));
+        writer.write(Arc::new(AnotherTopic::default())).unwrap();
+
+        // a timeout reported without a write does not make up a blocking time
+        let timeout: WriterError = DDSError::Timeout.into();
+        assert!(matches!(
+            timeout,
+            WriterError::Timeout {
+                blocked_for: None,
+                ..
+            }
+        ));
+        assert_eq!(timeout.to_string(), "Write timed out");
+        // DDS_RETCODE_TIMEOUT
+        let timeout = write_error(-10, Duration::from_millis(5));
+        assert_eq!(
+            timeout.to_string(),
+            "Write timed out after blocking for 5ms"
+        );
+    }
+
+    #[test]
+    fn test_loan_unsupported() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic = TestTopic::create_topic(&participant, None, None, None).unwrap();
+        let mut writer = DdsWriter::create(&participant, topic, None, None).unwrap();
+
+        // a fixed size type only gets a loan if Cyclone uses shared memory
+        match writer.loan() {
+            Ok(loaned) => writer.return_loan(loaned).unwrap(),
+            Err(WriterError::LoanUnsupported) => {}
+            Err(e) => panic!("Unexpected loan error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_wait_for_readers() {
+        let participant = DdsParticipant::create(None, None, None).unwrap();
+        let topic = AnotherTopic::create_topic(&participant, Some("matched"), None, None).unwrap();
+        let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+        let writer = DdsWriter::create(&publisher, topic.clone(), None, None).unwrap();
+
+        assert_eq!(
+            writer.publication_matched_status().unwrap().current_count,
+            0
+        );
+
+        let rt = Runtime::new().unwrap();
+        let timed_out = rt.block_on(writer.wait_for_readers(1, Duration::from_millis(100)));
+        assert_eq!(timed_out, Err(DDSError::Timeout));
+
+        let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+        let _reader = DdsReader::create(&subscriber, topic, None, None).unwrap();
+
+        let status = rt
+            .block_on(writer.wait_for_readers(1, Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(status.current_count, 1);
+
+        let matched = writer.matched_subscriptions().unwrap();
+        assert_eq!(matched.len(), 1);
+        assert_eq!(
+            matched[0].topic_name,
+            AnotherTopic::topic_name(Some("matched"))
+        );
+        assert_eq!(
+            matched[0].type_name,
+            AnotherTopic::typename().into_string().unwrap()
+        );
+    }
+
     //#[test]
     fn test_loan() {
         // Make sure iox-roudi is running
@@ -369,7 +688,7 @@ mod test {
             let _another_task = tokio::spawn(async move {
                 let mut samples = TestTopic::create_sample_buffer(5);
                 if let Ok(t) = reader.take(&mut samples).await {
-                    assert_eq!(t, 1);
+                    assert_eq!(t.valid, 1);
                     for s in samples.iter() {
                         println!("Got sample {:?}", s);
                     }
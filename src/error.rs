@@ -25,3 +25,52 @@ pub enum ReaderError {
     #[error("DDS Binding error")]
     DdsError(#[from] crate::DDSError),
 }
+
+#[derive(Error, Debug, Clone)]
+pub enum WriterError {
+    #[error(
+        "Write timed out{}",
+        .blocked_for.map_or(String::new(), |d| format!(" after blocking for {:?}", d))
+    )]
+    Timeout {
+        /// How long the write blocked, if measured
+        blocked_for: Option<std::time::Duration>,
+        #[source]
+        source: crate::DDSError,
+    },
+    #[error("Writer resource limits reached")]
+    ResourceLimit(#[source] crate::DDSError),
+    #[error("Writer preconditions not met")]
+    PreconditionNotMet(#[source] crate::DDSError),
+    #[error("Sample serialization failed: {0}")]
+    SerializationFailed(String),
+    #[error("Loaning needs a fixed size type and a Cyclone with shared memory enabled")]
+    LoanUnsupported,
+    #[error("Writer has been deleted")]
+    Deleted(#[source] crate::DDSError),
+    #[error("DDS Binding error")]
+    DdsError(#[source] crate::DDSError),
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum QueryConditionError {
+    #[error("No more than {0} query conditions can exist at the same time")]
+    TooManyConditions(usize),
+    #[error("DDS Binding error")]
+    DdsError(#[from] crate::DDSError),
+}
+
+impl From<crate::DDSError> for WriterError {
+    fn from(e: crate::DDSError) -> Self {
+        match e {
+            crate::DDSError::Timeout => WriterError::Timeout {
+                blocked_for: None,
+                source: e,
+            },
+            crate::DDSError::OutOfResources => WriterError::ResourceLimit(e),
+            crate::DDSError::PreconditionNotMet => WriterError::PreconditionNotMet(e),
+            crate::DDSError::AlreadyDeleted => WriterError::Deleted(e),
+            e => WriterError::DdsError(e),
+        }
+    }
+}
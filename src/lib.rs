@@ -22,30 +22,50 @@ pub mod alloc;
 mod common;
 pub mod dds_api;
 pub mod dds_domain;
+pub mod dds_instance_events;
 pub mod dds_listener;
+pub mod dds_listener_channel;
+pub mod dds_listener_traits;
+pub mod dds_loan;
 pub mod dds_participant;
 pub mod dds_publisher;
 pub mod dds_qos;
+pub mod dds_querycondition;
 pub mod dds_reader;
+pub mod dds_status;
+pub mod dds_status_events;
+pub mod dds_stream;
 pub mod dds_subscriber;
 pub mod dds_topic;
 mod dds_waitset;
 pub mod dds_writer;
 pub mod error;
 pub mod serdes;
+mod wakers;
 
 pub use common::{DdsReadable, DdsWritable, Entity};
 pub use dds_api::*;
+pub use dds_instance_events::{InstanceEvent, InstanceEventStream, InstanceEvents};
 pub use dds_listener::DdsListener;
+pub use dds_listener_channel::{DdsEvent, DdsEventReceiver, Overflow};
+pub use dds_listener_traits::{ParticipantListener, ReaderListener, WriterListener};
+pub use dds_loan::LoanedSamples;
 pub use dds_participant::{DdsParticipant, ParticipantBuilder};
 pub use dds_publisher::{DdsPublisher, PublisherBuilder};
 pub use dds_qos::*;
-pub use dds_reader::{DdsReadCondition, DdsReader, ReaderBuilder};
-pub use dds_subscriber::{DdsSubscriber, SubscriberBuilder};
+pub use dds_querycondition::{DdsQueryCondition, MAX_QUERY_CONDITIONS};
+pub use dds_reader::{DdsReadCondition, DdsReader, ReadResult, ReaderBuilder};
+pub use dds_status::*;
+pub use dds_status_events::StatusStream;
+pub use dds_stream::{SampleInfoStream, SampleStream, SharedSampleStream};
+pub use dds_subscriber::{DdsSubscriber, GroupAccess, SubscriberBuilder};
 pub use dds_topic::{DdsTopic, TopicBuilder};
 pub use dds_waitset::DdsWaitset;
 pub use dds_writer::{DdsWriter, WriterBuilder};
-pub use serdes::{Sample, SampleBuffer, TopicType};
+pub use serdes::{
+    InstanceState, Sample, SampleBuffer, SampleInfo, SampleState, TopicFixedSize, TopicType,
+    ViewState,
+};
 
 pub use cdr;
 pub use cyclonedds_sys::dds_error::DDSError;
@@ -24,7 +24,8 @@ use serde::{de::DeserializeOwned, Serialize};
 use std::io::prelude::*;
 use std::mem::MaybeUninit;
 use std::ptr::NonNull;
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{
     ffi::{c_void, CStr},
     marker::PhantomData,
@@ -32,6 +33,7 @@ use std::{
     sync::Arc,
 };
 
+use crate::dds_api::InstanceHandle;
 use cyclonedds_sys::*;
 //use fasthash::{murmur3::Hasher32, FastHasher};
 use murmur3::murmur3_32;
@@ -40,16 +42,86 @@ use std::io::Cursor;
 #[repr(C)]
 pub struct SerType<T> {
     sertype: ddsi_sertype,
+    // serialization buffers shared by all writers of this type
+    cdr_pool: CdrBufferPool,
+    // freed serdata kept for the next samples of this type. They stay boxed as
+    // Cyclone holds them by pointer.
+    serdata_pool: SerDataPool<T>,
     _phantom: PhantomData<T>,
 }
 
+/// Maximum number of idle serialization buffers kept by a pool
+const CDR_BUFFER_POOL_DEPTH: usize = 16;
+
+/// Maximum number of idle serdata kept by a sertype
+const SERDATA_POOL_DEPTH: usize = 16;
+
+#[allow(clippy::vec_box)]
+type SerDataPool<T> = Mutex<Vec<Box<SerData<T>>>>;
+
+/// A pool of serialization buffers. Outgoing samples are serialized into a
+/// buffer taken from the pool and the buffer is given back when Cyclone frees
+/// the serdata. Once the pool has warmed up, serializing samples of a bounded
+/// size does not allocate.
+pub(crate) struct CdrBufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl CdrBufferPool {
+    pub(crate) fn new() -> Self {
+        Self {
+            buffers: Mutex::new(Vec::with_capacity(CDR_BUFFER_POOL_DEPTH)),
+        }
+    }
+
+    /// Take an empty buffer with at least `capacity` bytes reserved.
+    pub(crate) fn take(&self, capacity: usize) -> Vec<u8> {
+        let maybe_buffer = self.buffers.lock().ok().and_then(|mut b| b.pop());
+        let mut buffer = maybe_buffer.unwrap_or_default();
+        buffer.clear();
+        buffer.reserve(capacity);
+        buffer
+    }
+
+    /// Give a buffer back to the pool. The buffer is dropped if the pool is full.
+    pub(crate) fn give(&self, buffer: Vec<u8>) {
+        if let Ok(mut buffers) = self.buffers.lock() {
+            if buffers.len() < CDR_BUFFER_POOL_DEPTH {
+                buffers.push(buffer);
+            }
+        }
+    }
+}
+
+thread_local! {
+    // Why the last sample written from this thread could not be serialized.
+    // Cyclone only sees a null serdata, so the reason is kept here for the writer.
+    static SERIALIZATION_ERROR: std::cell::RefCell<Option<String>> = const { std::cell::RefCell::new(None) };
+    // Encoding of the key of the last sample written from this thread, reused
+    // so that hashing the key of a written sample does not allocate.
+    static KEY_CDR: std::cell::RefCell<Vec<u8>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Take the reason the last sample written from this thread failed to serialize
+pub(crate) fn take_serialization_error() -> Option<String> {
+    SERIALIZATION_ERROR.with(|e| e.borrow_mut().take())
+}
+
 pub trait TopicType: Serialize + DeserializeOwned {
+    /// The key fields of the topic. The derive macros generate a
+    /// `<Name>KeyHolder_` struct for this.
+    type Key: Serialize + DeserializeOwned + Clone + for<'a> From<&'a Self>;
+
     // generate a non-cryptographic hash of the key values to be used internally
     // in cyclonedds
     fn hash(&self) -> u32 {
-        let cdr = self.key_cdr();
-        let mut cursor = Cursor::new(cdr.as_slice());
-        murmur3_32(&mut cursor, 0).unwrap()
+        key_cdr_hash(&self.key_cdr())
+    }
+
+    /// return the cdr encoding of a key. The encoded string includes the four byte
+    /// encapsulation header.
+    fn key_to_cdr(key: &Self::Key) -> Vec<u8> {
+        cdr::serialize::<_, _, CdrBe>(key, Infinite).expect("Unable to serialize key")
     }
 
     fn is_fixed_size() -> bool {
@@ -105,6 +177,16 @@ pub trait TopicType: Serialize + DeserializeOwned {
     fn force_md5_keyhash() -> bool;
 }
 
+/// Marker for topic types of a fixed size, whose samples can be loaned from
+/// Cyclone. Implemented by `#[derive(TopicFixedSize)]`.
+pub trait TopicFixedSize: TopicType {}
+
+// hash of the cdr encoded key as used by Cyclone to find instances
+fn key_cdr_hash(key_cdr: &[u8]) -> u32 {
+    let mut cursor = Cursor::new(key_cdr);
+    murmur3_32(&mut cursor, 0).unwrap()
+}
+
 impl<'a, T> SerType<T> {
     pub fn new() -> Box<SerType<T>>
     where
@@ -128,10 +210,34 @@ impl<'a, T> SerType<T> {
                     sertype
                 }
             },
+            cdr_pool: CdrBufferPool::new(),
+            serdata_pool: Mutex::new(Vec::with_capacity(SERDATA_POOL_DEPTH)),
             _phantom: PhantomData,
         })
     }
 
+    // get the serialization buffer pool of the sertype. The sertype must have
+    // been created by SerType<T>::new
+    fn cdr_pool_from_sertype(sertype: *const ddsi_sertype) -> Option<&'a CdrBufferPool> {
+        let ptr = sertype as *const SerType<T>;
+        if !ptr.is_null() {
+            Some(unsafe { &(*ptr).cdr_pool })
+        } else {
+            None
+        }
+    }
+
+    // get the pool of freed serdata of the sertype. The sertype must have
+    // been created by SerType<T>::new
+    fn serdata_pool_from_sertype(sertype: *const ddsi_sertype) -> Option<&'a SerDataPool<T>> {
+        let ptr = sertype as *const SerType<T>;
+        if !ptr.is_null() {
+            Some(unsafe { &(*ptr).serdata_pool })
+        } else {
+            None
+        }
+    }
+
     // cast into cyclone dds sertype.  Rust relinquishes ownership here.
     // Cyclone DDS will free this. But if you need to free this pointer
     // before handing it over to cyclone, make sure you explicitly free it
@@ -181,6 +287,8 @@ pub struct Sample<T> {
     serdata: Option<*mut ddsi_serdata>,
     // sample is used for outgoing samples.
     sample: Option<SampleStorage<T>>,
+    // key of a key only sample, used to look up instances
+    key_cdr: Option<Vec<u8>>,
 }
 
 impl<'a, T> Sample<T>
@@ -201,6 +309,35 @@ where
         }
     }
 
+    /// Move the data out of a received sample and release the serdata. The data
+    /// is shared with whatever else still references the serdata, such as
+    /// another reader, instead of being cloned.
+    pub(crate) fn take_shared(&mut self) -> Option<Arc<T>> {
+        let serdata = self.serdata.take()?;
+        let data = SerData::<T>::mut_ref_from_serdata(serdata);
+        let sample = match &data.sample {
+            SampleData::SDKData(it) => Some(it.clone()),
+            // Only fixed size types are loaned and the shared memory chunk never
+            // drops its sample, so a bitwise copy is a plain copy
+            SampleData::SHMData(it) => Some(Arc::new(unsafe { std::ptr::read(it.as_ptr()) })),
+            SampleData::Uninitialized | SampleData::SDKKey => None,
+        };
+        unsafe { ddsi_serdata_removeref(serdata) };
+        sample
+    }
+
+    /// Move the data out of a received sample and release the serdata. The data
+    /// is moved if nothing else references it, and cloned otherwise.
+    pub(crate) fn take_owned(&mut self) -> Option<T>
+    where
+        T: Clone,
+    {
+        // Once the serdata is released, the data is still shared only if Cyclone
+        // holds the serdata elsewhere, such as in the cache of another reader
+        self.take_shared()
+            .map(|it| Arc::try_unwrap(it).unwrap_or_else(|it| it.as_ref().clone()))
+    }
+
     pub fn get_sample(&self) -> Option<SampleStorage<T>> {
         //if let Ok(t) = self.sample.write() {
         match self.sample.as_ref() {
@@ -228,7 +365,10 @@ where
         unsafe {
             ddsi_serdata_addref(serdata);
         }
-        self.serdata = Some(serdata)
+        // and release the serdata of the sample previously held in this slot
+        if let Some(old) = self.serdata.replace(serdata) {
+            unsafe { ddsi_serdata_removeref(old) };
+        }
     }
 
     pub fn set(&mut self, t: Arc<T>) {
@@ -243,7 +383,11 @@ where
 
     pub fn clear(&mut self) {
         //let mut sample = self.sample.write().unwrap();
+        if let Some(serdata) = self.serdata.take() {
+            unsafe { ddsi_serdata_removeref(serdata) };
+        }
         let t = self.sample.take();
+        self.key_cdr = None;
 
         match &t {
             Some(SampleStorage::Owned(o)) => {}
@@ -256,6 +400,26 @@ where
         Self {
             serdata: None,
             sample: Some(SampleStorage::Owned(it)),
+            key_cdr: None,
+        }
+    }
+
+    /// The key of the instance of the sample. Invalid samples returned by a read
+    /// or take only hold the key.
+    pub fn key(&self) -> Option<T::Key> {
+        match (self.try_deref(), &self.key_cdr) {
+            (Some(sample), _) => Some(T::Key::from(sample)),
+            (None, Some(key_cdr)) => cdr::deserialize::<T::Key>(key_cdr).ok(),
+            (None, None) => None,
+        }
+    }
+
+    /// A sample that only holds a key, serialized with `TopicType::key_to_cdr`
+    pub(crate) fn from_key_cdr(key_cdr: Vec<u8>) -> Self {
+        Self {
+            serdata: None,
+            sample: None,
+            key_cdr: Some(key_cdr),
         }
     }
 }
@@ -265,6 +429,7 @@ impl<T> Default for Sample<T> {
         Self {
             serdata: None,
             sample: None,
+            key_cdr: None,
         }
     }
 }
@@ -277,6 +442,120 @@ impl<T> Drop for Sample<T> {
     }
 }
 
+/// Whether a sample has been read before
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleState {
+    Read,
+    NotRead,
+}
+
+/// Whether this is the first sample of a new generation of the instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewState {
+    New,
+    Old,
+}
+
+/// Liveliness of the instance a sample belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstanceState {
+    Alive,
+    Disposed,
+    NoWriters,
+}
+
+/// Information delivered by Cyclone with each sample. Cyclone does not
+/// provide a reception timestamp, only the source timestamp of the writer.
+#[repr(transparent)]
+#[derive(Clone, Copy, Default)]
+pub struct SampleInfo(dds_sample_info);
+
+impl SampleInfo {
+    // Cyclone writes a non zero sample state for every sample it returns
+    fn is_filled(&self) -> bool {
+        self.0.sample_state != 0
+    }
+
+    pub fn sample_state(&self) -> SampleState {
+        if self.0.sample_state == dds_sample_state_DDS_SST_READ {
+            SampleState::Read
+        } else {
+            SampleState::NotRead
+        }
+    }
+
+    pub fn view_state(&self) -> ViewState {
+        if self.0.view_state == dds_view_state_DDS_VST_NEW {
+            ViewState::New
+        } else {
+            ViewState::Old
+        }
+    }
+
+    #[allow(non_upper_case_globals)]
+    pub fn instance_state(&self) -> InstanceState {
+        match self.0.instance_state {
+            dds_instance_state_DDS_IST_NOT_ALIVE_DISPOSED => InstanceState::Disposed,
+            dds_instance_state_DDS_IST_NOT_ALIVE_NO_WRITERS => InstanceState::NoWriters,
+            _ => InstanceState::Alive,
+        }
+    }
+
+    /// False if the sample only notifies a change in the instance state
+    pub fn valid_data(&self) -> bool {
+        self.0.valid_data
+    }
+
+    /// The time at which the writer wrote the sample
+    pub fn source_timestamp(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_nanos(self.0.source_timestamp.max(0) as u64)
+    }
+
+    /// The instance the sample belongs to
+    pub fn instance_handle(&self) -> InstanceHandle {
+        self.0.instance_handle.into()
+    }
+
+    /// The writer that wrote the sample
+    pub fn publication_handle(&self) -> InstanceHandle {
+        self.0.publication_handle.into()
+    }
+
+    pub fn disposed_generation_count(&self) -> u32 {
+        self.0.disposed_generation_count
+    }
+
+    pub fn no_writers_generation_count(&self) -> u32 {
+        self.0.no_writers_generation_count
+    }
+
+    pub fn sample_rank(&self) -> u32 {
+        self.0.sample_rank
+    }
+
+    pub fn generation_rank(&self) -> u32 {
+        self.0.generation_rank
+    }
+
+    pub fn absolute_generation_rank(&self) -> u32 {
+        self.0.absolute_generation_rank
+    }
+}
+
+impl std::fmt::Debug for SampleInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SampleInfo")
+            .field("sample_state", &self.sample_state())
+            .field("view_state", &self.view_state())
+            .field("instance_state", &self.instance_state())
+            .field("valid_data", &self.valid_data())
+            .field("source_timestamp", &self.source_timestamp())
+            .field("instance_handle", &self.instance_handle())
+            .field("publication_handle", &self.publication_handle())
+            .finish()
+    }
+}
+
 ///
 /// TODO: UNSAFE WARNING Review needed. Forcing SampleBuffer<T> to be Send
 /// DDS read API uses an array of void* pointers. The SampleBuffer<T> structure
@@ -293,14 +572,14 @@ unsafe impl<T> Send for SampleBuffer<T> {}
 pub struct SampleBuffer<T> {
     /// This is !Send. This is the only way to punch through the Cyclone API as we need an array of pointers
     pub(crate) buffer: Vec<*mut Sample<T>>,
-    pub(crate) sample_info: Vec<cyclonedds_sys::dds_sample_info>,
+    pub(crate) sample_info: Vec<SampleInfo>,
 }
 
 impl<'a, T: TopicType> SampleBuffer<T> {
     pub fn new(len: usize) -> Self {
         let mut buf = Self {
             buffer: Vec::new(),
-            sample_info: vec![cyclonedds_sys::dds_sample_info::default(); len],
+            sample_info: vec![SampleInfo::default(); len],
         };
 
         for _i in 0..len {
@@ -313,7 +592,13 @@ impl<'a, T: TopicType> SampleBuffer<T> {
     /// Check if sample is valid. Will panic if out of
     /// bounds.
     pub fn is_valid_sample(&self, index: usize) -> bool {
-        self.sample_info[index].valid_data
+        self.sample_info[index].valid_data()
+    }
+
+    /// Get the sample info for a sample. Will panic if out of
+    /// bounds.
+    pub fn info(&self, index: usize) -> &SampleInfo {
+        &self.sample_info[index]
     }
 
     pub fn len(&self) -> usize {
@@ -328,6 +613,52 @@ impl<'a, T: TopicType> SampleBuffer<T> {
         p
     }
 
+    /// Iterate over the samples filled in by the last read or take together with
+    /// their sample info. Invalid samples (`valid_data() == false`) carry no data
+    /// and are yielded as `None`; they notify a change in the instance state, such
+    /// as a dispose, for the instance in `SampleInfo::instance_handle`.
+    pub fn iter_with_info(&'a self) -> impl Iterator<Item = (Option<&'a T>, &'a SampleInfo)> {
+        self.buffer
+            .iter()
+            .zip(self.sample_info.iter())
+            .filter(|(_, info)| info.is_filled())
+            .map(|(p, info)| {
+                let sample = unsafe { &*(*p) };
+                (sample.try_deref(), info)
+            })
+    }
+
+    /// Move the samples filled in by the last read or take out of the buffer,
+    /// together with their sample info, without cloning them. See `iter_with_info`.
+    pub(crate) fn take_shared_with_info(&mut self) -> Vec<(Option<Arc<T>>, SampleInfo)> {
+        self.buffer
+            .iter()
+            .zip(self.sample_info.iter())
+            .filter(|(_, info)| info.is_filled())
+            .map(|(p, info)| {
+                let sample = unsafe { &mut *(*p) };
+                (sample.take_shared(), *info)
+            })
+            .collect()
+    }
+
+    /// Move the samples filled in by the last read or take out of the buffer,
+    /// together with their sample info. See `iter_with_info`.
+    pub(crate) fn take_owned_with_info(&mut self) -> Vec<(Option<T>, SampleInfo)>
+    where
+        T: Clone,
+    {
+        self.buffer
+            .iter()
+            .zip(self.sample_info.iter())
+            .filter(|(_, info)| info.is_filled())
+            .map(|(p, info)| {
+                let sample = unsafe { &mut *(*p) };
+                (sample.take_owned(), *info)
+            })
+            .collect()
+    }
+
     /// Get a sample
     pub fn get(&self, index: usize) -> &Sample<T> {
         let p_sample = self.buffer[index];
@@ -338,7 +669,16 @@ impl<'a, T: TopicType> SampleBuffer<T> {
     /// to be used in unsafe code that calls the CycloneDDS
     /// API
     pub unsafe fn as_mut_ptr(&mut self) -> (*mut *mut Sample<T>, *mut dds_sample_info) {
-        (self.buffer.as_mut_ptr(), self.sample_info.as_mut_ptr())
+        // Forget the previous read so slots that Cyclone does not fill are not
+        // mistaken for fresh samples.
+        for (p, info) in self.buffer.iter().zip(self.sample_info.iter_mut()) {
+            (**p).clear();
+            *info = SampleInfo::default();
+        }
+        (
+            self.buffer.as_mut_ptr(),
+            self.sample_info.as_mut_ptr() as *mut dds_sample_info,
+        )
     }
 }
 
@@ -368,6 +708,8 @@ unsafe extern "C" fn zero_samples<T>(
 ) {
 } // empty implementation
 
+// Cyclone expects the samples in one contiguous block with `ptrs[i]` pointing
+// at sample `i`. Samples allocated here are freed by free_samples.
 #[allow(dead_code)]
 extern "C" fn realloc_samples<T>(
     ptrs: *mut *mut std::ffi::c_void,
@@ -377,36 +719,25 @@ extern "C" fn realloc_samples<T>(
     new_count: size_t,
 ) {
     //println!("realloc");
-    let old = unsafe {
-        Vec::<*mut Sample<T>>::from_raw_parts(
-            old as *mut *mut Sample<T>,
-            old_count as usize,
-            old_count as usize,
-        )
-    };
-    let mut new = Vec::<*mut Sample<T>>::with_capacity(new_count as usize);
-
-    if new_count >= old_count {
-        for entry in old {
-            new.push(entry);
-        }
-
-        for _i in 0..(new_count - old_count) {
-            new.push(Box::into_raw(Box::new(Sample::<T>::default())));
-        }
+    let mut samples = if old.is_null() {
+        Vec::new()
     } else {
-        for e in old.into_iter().take(new_count as usize) {
-            new.push(e)
+        unsafe {
+            Vec::<Sample<T>>::from_raw_parts(
+                old as *mut Sample<T>,
+                old_count as usize,
+                old_count as usize,
+            )
         }
-    }
-
-    let leaked = new.leak();
+    };
+    samples.resize_with(new_count as usize, Sample::default);
 
-    let (raw, _length) = (leaked.as_ptr(), leaked.len());
-    // if the length and allocated length are not equal, we messed up above.
-    //assert_eq!(length, allocated_length);
-    unsafe {
-        *ptrs = raw as *mut std::ffi::c_void;
+    // the block must be exactly new_count long to be freed again
+    let samples = Box::into_raw(samples.into_boxed_slice()) as *mut Sample<T>;
+    for i in 0..new_count as usize {
+        unsafe {
+            *ptrs.add(i) = samples.add(i) as *mut std::ffi::c_void;
+        }
     }
 }
 
@@ -599,11 +930,41 @@ where
     match kind {
         #[allow(non_upper_case_globals)]
         ddsi_serdata_kind_SDK_DATA => {
-            serdata.sample = SampleData::SDKData(sample.get().unwrap());
+            let sample = sample.get().unwrap();
+            if T::has_key() {
+                // local readers find the instance from the hashes of the written serdata
+                KEY_CDR.with(|buffer| {
+                    let mut key_cdr = buffer.borrow_mut();
+                    key_cdr.clear();
+                    let key = T::Key::from(sample.as_ref());
+                    cdr::serialize_into::<_, T::Key, _, CdrBe>(&mut *key_cdr, &key, Infinite)
+                        .expect("Unable to serialize key");
+                    serdata.serdata.hash = key_cdr_hash(&key_cdr);
+                    compute_key_hash(&key_cdr[4..], &mut serdata);
+                });
+            }
+            // Size the sample now so a failing Serialize impl is reported to the
+            // writing thread instead of being dropped when Cyclone serializes later.
+            match cdr::calc_serialized_size_bounded(sample.as_ref(), u32::MAX as u64) {
+                Ok(size) => serdata.serialized_size = Some(size as u32),
+                Err(e) => {
+                    SERIALIZATION_ERROR.with(|err| err.replace(Some(e.to_string())));
+                    return std::ptr::null_mut();
+                }
+            }
+            serdata.sample = SampleData::SDKData(sample);
         }
 
         ddsi_serdata_kind_SDK_KEY => {
-            panic!("Don't know how to create serdata from sample for SDK_KEY");
+            let key_cdr = match (&sample.key_cdr, sample.get_sample()) {
+                (Some(key_cdr), _) => key_cdr.clone(),
+                (None, Some(data)) => data.key_cdr(),
+                (None, None) => return std::ptr::null_mut(),
+            };
+            serdata.serdata.hash = key_cdr_hash(&key_cdr);
+            compute_key_hash(&key_cdr[4..], &mut serdata);
+            serdata.sample = SampleData::SDKKey;
+            serdata.key_cdr = Some(key_cdr);
         }
 
         _ => panic!("Unexpected kind"),
@@ -684,8 +1045,28 @@ unsafe extern "C" fn free_serdata<T>(serdata: *mut ddsi_serdata) {
         free_iox_chunk(iox_subscriber, chunk);
     }
 
-    let _data = Box::from_raw(ptr);
-    // _data goes out of scope and frees the SerData. Nothing more to do here.
+    let mut data = Box::from_raw(ptr);
+    // hand the serialization buffer back for the next sample of this type
+    if let Some(cdr) = data.cdr.take() {
+        if let Some(pool) = SerType::<T>::cdr_pool_from_sertype(data.serdata.type_) {
+            pool.give(cdr);
+        }
+    }
+    // keep the SerData for the next sample of this type. If the pool is full,
+    // data goes out of scope and frees the SerData.
+    if let Some(pool) = SerType::<T>::serdata_pool_from_sertype(data.serdata.type_) {
+        data.serdata.iox_chunk = std::ptr::null_mut();
+        data.serdata.iox_subscriber = std::ptr::null_mut();
+        data.sample = SampleData::Uninitialized;
+        data.key_hash = KeyHash::None;
+        data.serialized_size = None;
+        data.key_cdr = None;
+        if let Ok(mut serdatas) = pool.lock() {
+            if serdatas.len() < SERDATA_POOL_DEPTH {
+                serdatas.push(data);
+            }
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -698,11 +1079,9 @@ where
         SampleData::Uninitialized => 0,
         SampleData::SDKKey => serdata.key_hash.key_length() as u32,
         // This function asks for the serialized size so we do this even for SHM Data
-        SampleData::SDKData(sample) => {
-            serdata.serialized_size =
-                Some((cdr::calc_serialized_size::<T>(&sample.deref())) as u32);
-            *serdata.serialized_size.as_ref().unwrap()
-        }
+        SampleData::SDKData(sample) => *serdata
+            .serialized_size
+            .get_or_insert_with(|| cdr::calc_serialized_size::<T>(&sample.deref()) as u32),
         SampleData::SHMData(_sample) => {
             // we refuse to serialize SHM data so return 0
             0
@@ -804,7 +1183,8 @@ where
         }
         SampleData::SDKData(sample) => {
             if serdata.cdr.is_none() {
-                serdata.cdr = serialize_type::<T>(sample, serdata.serialized_size).ok();
+                let pool = SerType::<T>::cdr_pool_from_sertype(serdata.serdata.type_);
+                serdata.cdr = serialize_type::<T>(sample, serdata.serialized_size, pool).ok();
             }
             if let Some(cdr) = &serdata.cdr {
                 let offset = offset as usize;
@@ -825,7 +1205,9 @@ where
 
         SampleData::SHMData(sample) => {
             if serdata.cdr.is_none() {
-                serdata.cdr = serialize_type::<T>(sample.as_ref(), serdata.serialized_size).ok();
+                let pool = SerType::<T>::cdr_pool_from_sertype(serdata.serdata.type_);
+                serdata.cdr =
+                    serialize_type::<T>(sample.as_ref(), serdata.serialized_size, pool).ok();
             }
             if let Some(cdr) = &serdata.cdr {
                 let offset = offset as usize;
@@ -842,19 +1224,23 @@ where
     ddsi_serdata_addref(&serdata.serdata)
 }
 
-fn serialize_type<T: Serialize>(sample: &T, maybe_size: Option<u32>) -> Result<Vec<u8>, ()> {
-    if let Some(size) = maybe_size {
-        // Round up allocation to multiple of four
-        let size = (size + 3) & !3u32;
-        let mut buffer = Vec::<u8>::with_capacity(size as usize);
-        if let Ok(()) = cdr::serialize_into::<_, T, _, CdrBe>(&mut buffer, sample, Infinite) {
-            Ok(buffer)
-        } else {
-            Err(())
-        }
-    } else if let Ok(data) = cdr::serialize::<T, _, CdrBe>(sample, Infinite) {
-        Ok(data)
+fn serialize_type<T: Serialize>(
+    sample: &T,
+    maybe_size: Option<u32>,
+    maybe_pool: Option<&CdrBufferPool>,
+) -> Result<Vec<u8>, ()> {
+    // Round up allocation to multiple of four
+    let capacity = maybe_size.map_or(0, |size| ((size + 3) & !3u32) as usize);
+    let mut buffer = match maybe_pool {
+        Some(pool) => pool.take(capacity),
+        None => Vec::<u8>::with_capacity(capacity),
+    };
+    if let Ok(()) = cdr::serialize_into::<_, T, _, CdrBe>(&mut buffer, sample, Infinite) {
+        Ok(buffer)
     } else {
+        if let Some(pool) = maybe_pool {
+            pool.give(buffer);
+        }
         Err(())
     }
 }
@@ -971,7 +1357,10 @@ where
 }
 
 #[allow(dead_code)]
-unsafe extern "C" fn serdata_to_untyped<T>(serdata: *const ddsi_serdata) -> *mut ddsi_serdata {
+unsafe extern "C" fn serdata_to_untyped<T>(serdata: *const ddsi_serdata) -> *mut ddsi_serdata
+where
+    T: TopicType,
+{
     //println!("serdata_to_untyped {:?}", serdata);
     let serdata = SerData::<T>::mut_ref_from_serdata(serdata);
 
@@ -984,6 +1373,12 @@ unsafe extern "C" fn serdata_to_untyped<T>(serdata: *const ddsi_serdata) -> *mut
     //copy the hashes
     untyped_serdata.key_hash = serdata.key_hash.clone();
     untyped_serdata.serdata.hash = serdata.serdata.hash;
+    // Cyclone keeps the untyped serdata of an instance to give back its key
+    untyped_serdata.key_cdr = match &serdata.sample {
+        SampleData::SDKData(it) => Some(it.key_cdr()),
+        SampleData::SHMData(it) => Some(it.as_ref().key_cdr()),
+        SampleData::Uninitialized | SampleData::SDKKey => serdata.key_cdr.clone(),
+    };
 
     let ptr = Box::into_raw(untyped_serdata);
 
@@ -997,7 +1392,7 @@ unsafe extern "C" fn serdata_to_untyped<T>(serdata: *const ddsi_serdata) -> *mut
 #[allow(dead_code)]
 unsafe extern "C" fn untyped_to_sample<T>(
     _sertype: *const ddsi_sertype,
-    _serdata: *const ddsi_serdata,
+    serdata: *const ddsi_serdata,
     sample: *mut c_void,
     _buf: *mut *mut c_void,
     _buflim: *mut c_void,
@@ -1008,9 +1403,12 @@ where
     //println!("untyped to sample!");
     if !sample.is_null() {
         let mut sample = Box::<Sample<T>>::from_raw(sample as *mut Sample<T>);
-        // hmm. We don't store serialized data in serdata. I'm not really sure how
-        // to implement this. For now, invalidate the sample.
+        // Cyclone fills invalid samples and instance keys from the untyped
+        // serdata, which only holds the key.
         sample.clear();
+        sample.key_cdr = SerData::<T>::const_ref_from_serdata(serdata)
+            .key_cdr
+            .clone();
         // leak this as we don't want to deallocate it.
         let _leaked = Box::<Sample<T>>::into_raw(sample);
         true
@@ -1211,10 +1609,19 @@ pub(crate) struct SerData<T> {
     key_hash: KeyHash,
     // We store the serialized size here if available
     serialized_size: Option<u32>,
+    // cdr encoded key of a key only serdata, including the encapsulation header
+    key_cdr: Option<Vec<u8>>,
 }
 
 impl<'a, T> SerData<T> {
     fn new(sertype: *const ddsi_sertype, kind: u32) -> Box<SerData<T>> {
+        // a SerData freed earlier only needs its header initialized again
+        let pooled = SerType::<T>::serdata_pool_from_sertype(sertype)
+            .and_then(|pool| pool.lock().ok()?.pop());
+        if let Some(mut data) = pooled {
+            unsafe { ddsi_serdata_init(&mut data.serdata, sertype, kind) };
+            return data;
+        }
         Box::<SerData<T>>::new(SerData {
             serdata: {
                 let mut data = std::mem::MaybeUninit::uninit();
@@ -1227,6 +1634,7 @@ impl<'a, T> SerData<T> {
             cdr: None,
             key_hash: KeyHash::default(),
             serialized_size: None,
+            key_cdr: None,
         })
     }
 
@@ -1258,6 +1666,7 @@ impl<T> Clone for SerData<T> {
             cdr: self.cdr.clone(),
             key_hash: self.key_hash.clone(),
             serialized_size: self.serialized_size.clone(),
+            key_cdr: self.key_cdr.clone(),
         }
     }
 }
@@ -1342,6 +1751,37 @@ mod test {
     use serde_derive::{Deserialize, Serialize};
     use std::ffi::CString;
 
+    #[test]
+    fn serialize_with_pool_reuses_buffers() {
+        #[derive(Serialize, Deserialize, Default)]
+        struct Foo {
+            id: u32,
+            position: [f64; 3],
+            payload: [u8; 32],
+        }
+
+        let foo = Foo {
+            id: 42,
+            position: [1.0, 2.0, 3.0],
+            payload: [7; 32],
+        };
+        let size = cdr::calc_serialized_size(&foo) as u32;
+        let pool = CdrBufferPool::new();
+
+        let buffer = serialize_type(&foo, Some(size), Some(&pool)).unwrap();
+        assert_eq!(
+            buffer,
+            cdr::serialize::<_, _, CdrBe>(&foo, Infinite).unwrap()
+        );
+        let first = buffer.as_ptr();
+        pool.give(buffer);
+
+        // the buffer given back is used for the next sample
+        let buffer = serialize_type(&foo, Some(size), Some(&pool)).unwrap();
+        assert_eq!(buffer.as_ptr(), first);
+        pool.give(buffer);
+    }
+
     #[test]
     fn scatter_gather() {
         let a = vec![1, 2, 3, 4, 5, 6];
@@ -1564,4 +2004,42 @@ mod test {
             dds_delete(topic);
         }
     }
+
+    #[test]
+    fn realloc_samples_keeps_contiguous_block() {
+        #[derive(Serialize, Deserialize, Topic, Default)]
+        struct Foo {
+            #[topic_key]
+            id: u32,
+        }
+
+        let sample_at = |ptrs: &[*mut std::ffi::c_void], i: usize| unsafe {
+            &mut *(ptrs[i] as *mut Sample<Foo>)
+        };
+        let stride = std::mem::size_of::<Sample<Foo>>();
+        let mut ptrs = [std::ptr::null_mut(); 4];
+
+        realloc_samples::<Foo>(
+            ptrs.as_mut_ptr(),
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            0,
+            2,
+        );
+        sample_at(&ptrs, 1).set(Arc::new(Foo { id: 7 }));
+
+        // growing keeps the samples and the block stays contiguous
+        realloc_samples::<Foo>(ptrs.as_mut_ptr(), std::ptr::null(), ptrs[0], 2, 4);
+        for i in 1..4 {
+            assert_eq!(ptrs[i] as usize - ptrs[i - 1] as usize, stride);
+        }
+        assert_eq!(sample_at(&ptrs, 1).try_deref().unwrap().id, 7);
+        assert!(sample_at(&ptrs, 3).try_deref().is_none());
+
+        // shrinking drops the samples past the end
+        realloc_samples::<Foo>(ptrs.as_mut_ptr(), std::ptr::null(), ptrs[0], 4, 2);
+        assert_eq!(sample_at(&ptrs, 1).try_deref().unwrap().id, 7);
+
+        free_samples::<Foo>(std::ptr::null(), ptrs.as_mut_ptr(), 2, DDS_FREE_ALL_BIT);
+    }
 }
@@ -0,0 +1,124 @@
+/*
+    Copyright 2021 Sojan James
+
+    Licensed under the Apache License, Version 2.0 (the "License");
+    you may not use this file except in compliance with the License.
+    You may obtain a copy of the License at
+
+        http://www.apache.org/licenses/LICENSE-2.0
+
+    Unless required by applicable law or agreed to in writing, software
+    distributed under the License is distributed on an "AS IS" BASIS,
+    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+    See the License for the specific language governing permissions and
+    limitations under the License.
+*/
+
+use std::task::Waker;
+
+/// The wakers of the tasks waiting on an entity. When the entity signals, all
+/// waiting tasks are woken in the order in which they started waiting. A task
+/// that finds nothing to do registers again, so no wake-up is lost when several
+/// tasks wait on the same entity.
+pub(crate) struct WakerRegistry {
+    wakers: Vec<Waker>,
+    closed: bool,
+}
+
+impl WakerRegistry {
+    pub(crate) fn new() -> Self {
+        Self {
+            wakers: Vec::new(),
+            closed: false,
+        }
+    }
+
+    /// Register the waker of a task. A task that polls again while waiting
+    /// keeps its place.
+    pub(crate) fn register(&mut self, waker: &Waker) {
+        if !self.wakers.iter().any(|w| w.will_wake(waker)) {
+            self.wakers.push(waker.clone());
+        }
+    }
+
+    /// Wake all waiting tasks
+    pub(crate) fn wake_all(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    /// Wake all waiting tasks for the last time, the entity is gone
+    pub(crate) fn close(&mut self) {
+        self.closed = true;
+        self.wake_all();
+    }
+
+    /// True once the entity is gone
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Number of tasks waiting
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.wakers.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_wake_all_registered() {
+        let counters: Vec<_> = (0..3)
+            .map(|_| Arc::new(CountingWaker(AtomicUsize::new(0))))
+            .collect();
+        let wakers: Vec<Waker> = counters.iter().map(|c| Waker::from(c.clone())).collect();
+
+        let mut registry = WakerRegistry::new();
+        for waker in &wakers {
+            registry.register(waker);
+        }
+        // registering again does not add a second entry
+        registry.register(&wakers[0]);
+        assert_eq!(registry.len(), 3);
+
+        registry.wake_all();
+        assert_eq!(registry.len(), 0);
+        for counter in &counters {
+            assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        }
+
+        // woken tasks must register again to be woken
+        registry.register(&wakers[1]);
+        registry.wake_all();
+        assert_eq!(counters[0].0.load(Ordering::SeqCst), 1);
+        assert_eq!(counters[1].0.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_close_wakes_for_the_last_time() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let mut registry = WakerRegistry::new();
+        registry.register(&Waker::from(counter.clone()));
+        assert!(!registry.is_closed());
+
+        registry.close();
+        assert!(registry.is_closed());
+        assert_eq!(registry.len(), 0);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+    }
+}
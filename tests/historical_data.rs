@@ -0,0 +1,69 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use cdds_derive::Topic;
+use cyclonedds_rs::{
+    dds_durability_kind, dds_history_kind, dds_reliability_kind, DDSError, DdsListener,
+    DdsParticipant, DdsPublisher, DdsQos, DdsReader, DdsSubscriber, DdsTopic, DdsWriter,
+    SampleBuffer, TopicType,
+};
+use serde_derive::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+#[derive(Serialize, Deserialize, Topic, Debug, Clone, PartialEq, Default)]
+struct Setting {
+    #[topic_key]
+    id: u32,
+    value: String,
+}
+
+fn durable_qos() -> DdsQos {
+    let mut qos = DdsQos::create().unwrap();
+    qos.set_durability(dds_durability_kind::DDS_DURABILITY_TRANSIENT_LOCAL)
+        .set_history(dds_history_kind::DDS_HISTORY_KEEP_LAST, 10)
+        .set_reliability(
+            dds_reliability_kind::DDS_RELIABILITY_RELIABLE,
+            Duration::from_millis(100),
+        );
+    qos
+}
+
+/// Readers created after the writes get the samples of a transient-local writer replayed
+#[test]
+fn late_joiner_gets_historical_data() {
+    let participant = DdsParticipant::create(None, None, None).unwrap();
+    let topic = Setting::create_topic(&participant, Some("historical_data"), None, None).unwrap();
+    let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+    let mut writer =
+        DdsWriter::create(&publisher, topic.clone(), Some(durable_qos()), None).unwrap();
+    for id in 0..3 {
+        let value = format!("setting {}", id);
+        writer.write(Arc::new(Setting { id, value })).unwrap();
+    }
+
+    let subscriber = DdsSubscriber::create(&participant, None, None).unwrap();
+    let reader = DdsReader::create(&subscriber, topic.clone(), Some(durable_qos()), None).unwrap();
+    reader
+        .wait_for_historical_data(Duration::from_secs(1))
+        .unwrap();
+    let mut samples = Setting::create_sample_buffer(5);
+    assert_eq!(reader.take_now(&mut samples).unwrap().valid, 3);
+
+    let rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        let reader = DdsReader::create_async(&subscriber, topic, Some(durable_qos())).unwrap();
+        reader
+            .wait_for_historical_data_async(Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(reader.take_now(&mut samples).unwrap().valid, 3);
+        let mut replayed: Vec<_> = samples.iter().map(|s| (s.id, s.value.clone())).collect();
+        replayed.sort_unstable();
+        assert_eq!(
+            replayed,
+            (0..3)
+                .map(|id| (id, format!("setting {}", id)))
+                .collect::<Vec<_>>()
+        );
+    });
+}
@@ -0,0 +1,135 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use cdds_derive::Topic;
+use cyclonedds_rs::{
+    dds_reliability_kind, DDSError, DdsListener, DdsParticipant, DdsPublisher, DdsQos, DdsReader,
+    DdsTopic, DdsWriter, SampleBuffer, TopicType,
+};
+use serde_derive::{Deserialize, Serialize};
+
+// Counts the allocations made by each thread. This replaces the allocator of
+// the whole test binary, so this file holds no other tests than the reader
+// the allocation test starts in a second process.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|a| a.get())
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[derive(Serialize, Deserialize, Topic, Debug, Default)]
+struct Frame {
+    #[topic_key]
+    camera: u32,
+    pixels: Vec<u8>,
+}
+
+const FRAME_SIZE: usize = 4096;
+const TOPIC_NAME: &str = "write_allocations";
+// set for the process running remote_reader
+const REMOTE_READER_ENV: &str = "CYCLONEDDS_RS_REMOTE_READER";
+
+fn best_effort() -> DdsQos {
+    let mut qos = DdsQos::create().unwrap();
+    qos.set_reliability(
+        dds_reliability_kind::DDS_RELIABILITY_BEST_EFFORT,
+        Duration::from_millis(100),
+    );
+    qos
+}
+
+/// Once the pools have warmed up, writing a sample to a reader in another
+/// process serializes it without allocating. The allocations of Cyclone itself
+/// are not counted, only those of the Rust side.
+#[test]
+fn write_reuses_serdata_and_serialization_buffers() {
+    // Cyclone delivers to readers in the same process without serializing,
+    // so the reader lives in a second process running this binary.
+    let mut remote_reader = Command::new(std::env::current_exe().unwrap())
+        .args(["remote_reader", "--exact", "--ignored"])
+        .env(REMOTE_READER_ENV, "1")
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let participant = DdsParticipant::create(None, None, None).unwrap();
+    let topic = Frame::create_topic(&participant, Some(TOPIC_NAME), None, None).unwrap();
+    let publisher = DdsPublisher::create(&participant, None, None).unwrap();
+    // a best effort writer does not keep the samples once they are sent
+    let mut writer = DdsWriter::create(&publisher, topic, Some(best_effort()), None).unwrap();
+    let matched = tokio::runtime::Runtime::new()
+        .unwrap()
+        .block_on(writer.wait_for_readers(1, Duration::from_secs(10)));
+    if matched.is_err() {
+        let _ = remote_reader.kill();
+        panic!("the remote reader did not match: {:?}", matched);
+    }
+
+    let frame = Arc::new(Frame {
+        camera: 1,
+        pixels: vec![0x55; FRAME_SIZE],
+    });
+
+    // warm up the pools
+    for _ in 0..100 {
+        writer.write(frame.clone()).unwrap();
+    }
+
+    let before = allocations();
+    for _ in 0..1000 {
+        writer.write(frame.clone()).unwrap();
+    }
+    let allocated = allocations() - before;
+
+    let _ = remote_reader.kill();
+    let _ = remote_reader.wait();
+    assert_eq!(
+        allocated, 0,
+        "writing 1000 samples allocated {} times",
+        allocated
+    );
+}
+
+/// The reader matched by write_reuses_serdata_and_serialization_buffers. It
+/// only runs in the process started by that test.
+#[test]
+#[ignore]
+fn remote_reader() {
+    if std::env::var_os(REMOTE_READER_ENV).is_none() {
+        return;
+    }
+    let participant = DdsParticipant::create(None, None, None).unwrap();
+    let topic = Frame::create_topic(&participant, Some(TOPIC_NAME), None, None).unwrap();
+    let _reader = DdsReader::create(&participant, topic, Some(best_effort()), None).unwrap();
+    // the writing test kills this process when it is done
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_secs(60) {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}